@@ -0,0 +1,198 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use image::{
+    codecs::{
+        gif::{GifDecoder, GifEncoder, Repeat},
+        png::PngDecoder,
+        webp::WebPDecoder,
+    },
+    AnimationDecoder, Delay, DynamicImage, Frame, Frames, ImageFormat, ImageResult, RgbaImage,
+};
+
+///Browsers play frames without a (sensible) delay at this speed, so we do too.
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const ANSI_CLEAR_SCREEN: &str = "\x1b[2J";
+const ANSI_CURSOR_HOME: &str = "\x1b[H";
+
+///Decodes every frame of an animated GIF, PNG or WebP together with how long it's shown.
+///Any other image comes back as a single frame, just like animations when `animated` is `false`.
+pub fn decode_frames(
+    path: impl AsRef<Path>,
+    animated: bool,
+) -> ImageResult<Vec<(DynamicImage, Duration)>> {
+    let path = path.as_ref();
+    decode_frame_bytes(
+        &fs::read(path)?,
+        ImageFormat::from_path(path).ok(),
+        animated,
+    )
+}
+
+///Like [`decode_frames`], for an image that's already in memory.
+///The format is sniffed from the bytes, `format` is only used for formats that can't be sniffed.
+pub fn decode_frame_bytes(
+    bytes: &[u8],
+    format: Option<ImageFormat>,
+    animated: bool,
+) -> ImageResult<Vec<(DynamicImage, Duration)>> {
+    let format = image::guess_format(bytes).ok().or(format);
+    //Frames are decoded lazily, so this skips every frame after the first.
+    let limit = if animated { usize::MAX } else { 1 };
+    let collect = |frames: Frames| frames.take(limit).collect::<ImageResult<Vec<Frame>>>();
+    let frames = match format {
+        Some(ImageFormat::Gif) => collect(GifDecoder::new(bytes)?.into_frames())?,
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(bytes)?;
+            if decoder.is_apng() {
+                collect(decoder.apng().into_frames())?
+            } else {
+                Vec::new()
+            }
+        }
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(bytes)?;
+            if decoder.has_animation() {
+                collect(decoder.into_frames())?
+            } else {
+                Vec::new()
+            }
+        }
+        _ => Vec::new(),
+    };
+    if frames.is_empty() {
+        let image = match format {
+            Some(format) => image::load_from_memory_with_format(bytes, format)?,
+            None => image::load_from_memory(bytes)?,
+        };
+        return Ok(vec![(image, Duration::ZERO)]);
+    }
+    Ok(frames
+        .into_iter()
+        .map(|frame| {
+            let delay = Duration::from(frame.delay());
+            (DynamicImage::ImageRgba8(frame.into_buffer()), delay)
+        })
+        .collect())
+}
+
+///How long to show a frame, either at a fixed `fps` or for its own `delay`.
+///Falls back to `delay` when a frame at `fps` would last longer than a [`Duration`] can hold.
+pub fn frame_delay(delay: Duration, fps: Option<f32>) -> Duration {
+    let fixed = fps
+        .filter(|fps| *fps > 0.0)
+        .and_then(|fps| Duration::try_from_secs_f32(1.0 / fps).ok());
+    match fixed {
+        Some(fixed) => fixed,
+        None if delay < MIN_FRAME_DELAY => DEFAULT_FRAME_DELAY,
+        None => delay,
+    }
+}
+
+///Draws the frames over each other by moving the cursor back to the top left corner.
+///Plays the animation `loops` times, or forever if `loops` is 0.
+pub fn play(frames: &[(String, Duration)], loops: u32, out: &mut impl Write) -> io::Result<()> {
+    write!(out, "{}", ANSI_CLEAR_SCREEN)?;
+    let mut played = 0;
+    while loops == 0 || played < loops {
+        for (frame, delay) in frames {
+            write!(out, "{}{}", ANSI_CURSOR_HOME, frame)?;
+            out.flush()?;
+            thread::sleep(*delay);
+        }
+        played += 1;
+    }
+    Ok(())
+}
+
+///Saves the frames as an animated GIF that plays `loops` times, or forever if `loops` is 0.
+pub fn save_gif(
+    path: impl AsRef<Path>,
+    frames: Vec<(RgbaImage, Duration)>,
+    loops: u32,
+) -> ImageResult<()> {
+    write_gif(BufWriter::new(File::create(path)?), frames, loops)
+}
+
+///Like [`save_gif`], for any writer.
+pub fn write_gif(
+    out: impl Write,
+    frames: Vec<(RgbaImage, Duration)>,
+    loops: u32,
+) -> ImageResult<()> {
+    let mut encoder = GifEncoder::new(out);
+    encoder.set_repeat(if loops == 0 {
+        Repeat::Infinite
+    } else {
+        Repeat::Finite((loops - 1).min(u16::MAX as u32) as u16)
+    })?;
+    encoder.encode_frames(frames.into_iter().map(|(image, delay)| {
+        Frame::from_parts(image, 0, 0, Delay::from_saturating_duration(delay))
+    }))
+}
+
+#[cfg(test)]
+mod animation_tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn frame_delay() {
+        let delay = Duration::from_millis(50);
+        assert_eq!(super::frame_delay(delay, None), delay);
+        assert_eq!(
+            super::frame_delay(Duration::ZERO, None),
+            DEFAULT_FRAME_DELAY
+        );
+        assert_eq!(
+            super::frame_delay(delay, Some(4.0)),
+            Duration::from_millis(250)
+        );
+        assert_eq!(super::frame_delay(delay, Some(1e-30)), delay);
+    }
+
+    #[test]
+    fn save_and_decode_gif() {
+        let path = std::env::temp_dir().join("char_art_animation_test.gif");
+        let frames = vec![
+            (
+                RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255])),
+                Duration::from_millis(50),
+            ),
+            (
+                RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255])),
+                Duration::from_millis(150),
+            ),
+        ];
+        save_gif(&path, frames, 0).unwrap();
+
+        let decoded = decode_frames(&path, true).unwrap();
+        let first = decode_frames(&path, false).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(first.len(), 1);
+        assert_eq!(decoded[1].1, Duration::from_millis(150));
+        assert_eq!(decoded[1].0.to_luma8().get_pixel(0, 0)[0], 255);
+    }
+
+    #[test]
+    fn play() {
+        let frames = vec![
+            ("a\n".to_string(), Duration::ZERO),
+            ("b\n".to_string(), Duration::ZERO),
+        ];
+        let mut out = Vec::new();
+        super::play(&frames, 2, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[2J\x1b[Ha\n\x1b[Hb\n\x1b[Ha\n\x1b[Hb\n"
+        );
+    }
+}
@@ -1,61 +1,198 @@
-use crate::brightness_char_map::BrightnessCharMap;
-use image::{imageops::resize, DynamicImage, GrayImage, Luma};
-use imageproc::drawing::{draw_text_mut, text_size};
+use crate::{
+    braille::{draw_braille_dots, is_braille},
+    brightness_char_map::BrightnessCharMap,
+    char_grid::{Cell, CharGrid},
+    char_mapper::{map_image, CharMapper},
+    dither::Dither,
+    shape_char_map::ShapeCharMap,
+};
+use image::{
+    imageops::resize, DynamicImage, GenericImageView, GrayImage, ImageBuffer, Pixel, Rgba,
+    RgbaImage,
+};
+use imageproc::{
+    drawing::{draw_filled_rect_mut, draw_text_mut},
+    rect::Rect,
+};
 use rusttype::{Font, Scale};
 
 pub trait AsChars {
-    const HEIGHT_SHRINK_AMOUNT: u32;
-    fn as_chars(&self, char_map: &BrightnessCharMap) -> String;
+    fn as_chars(&self, char_map: &BrightnessCharMap) -> CharGrid;
+    ///Like [`AsChars::as_chars`], but spreads the difference between each pixel and its char's
+    ///brightness over the neighbouring chars.
+    fn as_dithered_chars(&self, char_map: &BrightnessCharMap, dither: Dither) -> CharGrid;
+    ///Replaces every glyph sized tile of the image with the char that matches its shape best.
+    fn as_shape_chars(&self, shape_map: &ShapeCharMap) -> CharGrid;
+    ///Cuts the image into blocks and lets `mapper` pick the styled char of every block.
+    fn as_mapped_chars(&self, mapper: &dyn CharMapper) -> CharGrid;
 }
 
 impl AsChars for GrayImage {
-    const HEIGHT_SHRINK_AMOUNT: u32 = 2;
-
-    fn as_chars(&self, char_map: &BrightnessCharMap) -> String {
-        let image = resize(
-            self,
-            self.width(),
-            self.height() / Self::HEIGHT_SHRINK_AMOUNT,
-            image::imageops::FilterType::Lanczos3,
-        );
+    fn as_chars(&self, char_map: &BrightnessCharMap) -> CharGrid {
+        self.as_mapped_chars(char_map)
+    }
 
-        let image_width = image.width() as usize;
-        let mut char_image = String::with_capacity(image.len() + image.height() as usize + 2);
-        for (index, brightness) in image.iter().enumerate() {
-            unsafe {
-                char_image.push(char_map.get_unchecked(*brightness));
-            };
-            if (index + 1) % image_width == 0 {
-                char_image.push('\n');
-            }
+    fn as_dithered_chars(&self, char_map: &BrightnessCharMap, dither: Dither) -> CharGrid {
+        let levels = char_map
+            .brightnesses()
+            .iter()
+            .map(|(_, brightness)| *brightness)
+            .collect::<Vec<u8>>();
+        let source = shrink_height(self, char_map.cell_aspect());
+        let mut image = source.clone();
+        for pixel in image.iter_mut() {
+            *pixel = char_map.curve()[*pixel as usize];
         }
-        char_image
+        brightnesses_to_chars(&source, &dither.apply(&image, &levels), char_map)
+    }
+
+    fn as_shape_chars(&self, shape_map: &ShapeCharMap) -> CharGrid {
+        self.as_mapped_chars(shape_map)
+    }
+
+    fn as_mapped_chars(&self, mapper: &dyn CharMapper) -> CharGrid {
+        map_image(&DynamicImage::ImageLuma8(self.clone()), mapper)
     }
 }
 
-impl AsChars for DynamicImage {
-    const HEIGHT_SHRINK_AMOUNT: u32 = 2;
+///Squashes `image` vertically so every pixel ends up as one char that is `cell_aspect` times taller than wide.
+pub fn shrink_height<I: GenericImageView>(
+    image: &I,
+    cell_aspect: f32,
+) -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>
+where
+    I::Pixel: 'static,
+{
+    resize(
+        image,
+        image.width(),
+        ((image.height() as f32 / cell_aspect).round() as u32).max(1),
+        image::imageops::FilterType::Lanczos3,
+    )
+}
 
-    fn as_chars(&self, char_map: &BrightnessCharMap) -> String {
+///Looks up the char of every pixel in `quantized`, remembering the matching pixel of `source`.
+fn brightnesses_to_chars(
+    source: &GrayImage,
+    quantized: &GrayImage,
+    char_map: &BrightnessCharMap,
+) -> CharGrid {
+    let mut cells = Vec::with_capacity(quantized.len());
+    for (source, brightness) in source.iter().zip(quantized.iter()) {
+        let char = unsafe { char_map.get_unchecked(*brightness) };
+        cells.push(Cell::new(char, *source));
+    }
+    CharGrid::new(
+        quantized.width() as usize,
+        quantized.height() as usize,
+        cells,
+    )
+}
+
+impl AsChars for DynamicImage {
+    fn as_chars(&self, char_map: &BrightnessCharMap) -> CharGrid {
         self.to_luma8().as_chars(char_map)
     }
+
+    fn as_dithered_chars(&self, char_map: &BrightnessCharMap, dither: Dither) -> CharGrid {
+        self.to_luma8().as_dithered_chars(char_map, dither)
+    }
+
+    fn as_shape_chars(&self, shape_map: &ShapeCharMap) -> CharGrid {
+        self.as_mapped_chars(shape_map)
+    }
+
+    fn as_mapped_chars(&self, mapper: &dyn CharMapper) -> CharGrid {
+        map_image(self, mapper)
+    }
 }
 
-pub fn as_chars_image(chars: &str, font: &Font, scale: Scale) -> GrayImage {
-    let rows = chars.split('\n').collect::<Vec<&str>>();
-    let text_size = text_size(scale, font, rows[0]);
-    let mut image = GrayImage::new(text_size.0 as u32, text_size.1 as u32 * rows.len() as u32);
-    for (y, line) in rows.iter().enumerate() {
-        draw_text_mut(
-            &mut image,
-            Luma([255]),
-            0,
-            text_size.1 * y as i32,
-            scale,
-            font,
-            line,
+///Extra space between and around the cells of a char image.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GridLayout {
+    ///Pixels added between neighbouring chars, negative values pull them together.
+    pub letter_spacing: f32,
+    ///Pixels added between rows, negative values pull them together.
+    pub line_spacing: f32,
+    ///Pixels of background around the chars.
+    pub padding: u32,
+}
+
+impl GridLayout {
+    ///Width and height of a single cell.
+    ///Every cell is as wide as the widest advance of the grid's chars and as tall as a line of `font`.
+    pub fn cell_size(&self, chars: &CharGrid, font: &Font, scale: Scale) -> (f32, f32) {
+        let advance = chars
+            .cells()
+            .iter()
+            .map(|cell| cell.char)
+            .filter(|char| !is_braille(*char))
+            .chain([' '])
+            .map(|char| font.glyph(char).scaled(scale).h_metrics().advance_width)
+            .fold(0.0, f32::max);
+        let v_metrics = font.v_metrics(scale);
+        let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+        (
+            (advance + self.letter_spacing).max(1.0),
+            (line_height + self.line_spacing).max(1.0),
         )
     }
+}
+
+///Draws the chars on a fixed grid, in `foreground` on top of `background`.
+///Cells with colours of their own use those instead.
+///Give the background an alpha of 0 to get a transparent image.
+pub fn as_chars_image(
+    chars: &CharGrid,
+    font: &Font,
+    scale: Scale,
+    layout: GridLayout,
+    foreground: Rgba<u8>,
+    background: Rgba<u8>,
+) -> RgbaImage {
+    let (cell_width, cell_height) = layout.cell_size(chars, font, scale);
+    let padding = layout.padding as f32;
+    let mut image = RgbaImage::from_pixel(
+        (cell_width * chars.width() as f32 + padding * 2.0).ceil() as u32,
+        (cell_height * chars.height() as f32 + padding * 2.0).ceil() as u32,
+        background,
+    );
+    for (y, row) in chars.rows().enumerate() {
+        let top = padding + y as f32 * cell_height;
+        for (x, cell) in row.iter().enumerate() {
+            let left = padding + x as f32 * cell_width;
+            if let Some(color) = cell.background {
+                let width = (left + cell_width) as i32 - left as i32;
+                let height = (top + cell_height) as i32 - top as i32;
+                draw_filled_rect_mut(
+                    &mut image,
+                    Rect::at(left as i32, top as i32)
+                        .of_size(width.max(1) as u32, height.max(1) as u32),
+                    color.to_rgba(),
+                );
+            }
+            let color = cell.foreground.map_or(foreground, |color| color.to_rgba());
+            if is_braille(cell.char) {
+                draw_braille_dots(
+                    &mut image,
+                    cell.char,
+                    (left, top),
+                    (cell_width, cell_height),
+                    color,
+                );
+            } else if !cell.char.is_whitespace() {
+                draw_text_mut(
+                    &mut image,
+                    color,
+                    left as i32,
+                    top as i32,
+                    scale,
+                    font,
+                    &cell.char.to_string(),
+                );
+            }
+        }
+    }
     image
 }
 
@@ -65,6 +202,44 @@ mod brightness_char_map_tests {
 
     use super::*;
 
+    #[test]
+    fn as_chars_image_layout() {
+        let font = Font::try_from_bytes(crate::brightness_char_map::FONT).unwrap();
+        let scale = Scale::uniform(20.0);
+        let chars = CharGrid::from_text("a\ng_ j");
+        let layout = GridLayout {
+            padding: 3,
+            ..GridLayout::default()
+        };
+        let (cell_width, cell_height) = layout.cell_size(&chars, &font, scale);
+        let image = as_chars_image(
+            &chars,
+            &font,
+            scale,
+            layout,
+            Rgba([255, 255, 255, 255]),
+            Rgba([0, 0, 0, 255]),
+        );
+        assert_eq!(
+            image.dimensions(),
+            (
+                (cell_width * 4.0 + 6.0).ceil() as u32,
+                (cell_height * 2.0 + 6.0).ceil() as u32
+            )
+        );
+        assert_eq!(image.get_pixel(1, 1), &Rgba([0, 0, 0, 255]));
+
+        let spaced = GridLayout {
+            letter_spacing: 2.0,
+            line_spacing: 4.0,
+            padding: 0,
+        };
+        assert_eq!(
+            spaced.cell_size(&chars, &font, scale),
+            (cell_width + 2.0, cell_height + 4.0)
+        );
+    }
+
     #[test]
     fn as_chars() {
         let char_map = BrightnessCharMap::default();
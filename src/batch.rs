@@ -0,0 +1,199 @@
+use std::{
+    any::Any,
+    fs, io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use image::ImageFormat;
+
+///Turns every input into the files it stands for.
+///Directories expand to the images directly inside them and patterns containing `*` or `?`
+///expand to the matching files in their directory. Anything else is kept as is.
+pub fn expand_inputs(inputs: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            paths.extend(read_dir_sorted(path, |path| {
+                ImageFormat::from_path(path).is_ok()
+            })?);
+        } else if input.contains(['*', '?']) {
+            let directory = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            let pattern = path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+            paths.extend(read_dir_sorted(directory, |path| {
+                path.file_name()
+                    .is_some_and(|name| wildcard_match(&pattern, &name.to_string_lossy()))
+            })?);
+        } else {
+            paths.push(path.to_path_buf());
+        }
+    }
+    Ok(paths)
+}
+
+fn read_dir_sorted(directory: &Path, keep: impl Fn(&Path) -> bool) -> io::Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    paths.retain(|path| path.is_file() && keep(path));
+    paths.sort();
+    Ok(paths)
+}
+
+///Matches `name` against `pattern`, where `*` stands for any amount of chars and `?` for exactly one.
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+    let (mut p, mut n) = (0, 0);
+    //Where the last `*` was seen and how much of the name it has swallowed so far.
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(char) if *char == '?' || *char == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, swallowed)) => {
+                    p = star + 1;
+                    n = swallowed + 1;
+                    backtrack = Some((star, swallowed + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|char| *char == '*')
+}
+
+///Fills in an output path template for `input`, the `index`th input of the run.
+///`{stem}`, `{name}`, `{ext}` and `{index}` are replaced by the input's file stem, file name,
+///extension and position.
+pub fn output_path(template: &str, input: &Path, index: usize) -> PathBuf {
+    let part = |part: Option<&std::ffi::OsStr>| {
+        part.map(|part| part.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    PathBuf::from(
+        template
+            .replace("{stem}", &part(input.file_stem()))
+            .replace("{name}", &part(input.file_name()))
+            .replace("{ext}", &part(input.extension()))
+            .replace("{index}", &index.to_string()),
+    )
+}
+
+///Runs `job` on every item using `workers` threads and returns the results in the order of `items`.
+///A job that panics fails its own item with an error instead of the whole run.
+pub fn run_parallel<T: Sync, R: Send, E: Send + From<io::Error>>(
+    items: &[T],
+    workers: usize,
+    job: impl Fn(usize, &T) -> Result<R, E> + Sync,
+) -> Vec<Result<R, E>> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(
+        (0..items.len())
+            .map(|_| None)
+            .collect::<Vec<Option<Result<R, E>>>>(),
+    );
+    thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| job(index, item)))
+                    .unwrap_or_else(|payload| Err(panic_error(payload).into()));
+                results.lock().expect("a worker panicked")[index] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .expect("a worker panicked")
+        .into_iter()
+        .map(|result| result.expect("every item is handled by a worker"))
+        .collect()
+}
+
+fn panic_error(payload: Box<dyn Any + Send>) -> io::Error {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    };
+    io::Error::other(format!("panicked: {}", message))
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_match() {
+        assert!(super::wildcard_match("*.png", "sprite.png"));
+        assert!(super::wildcard_match("sprite_??.*", "sprite_01.gif"));
+        assert!(super::wildcard_match("*a*b", "xaxxab"));
+        assert!(!super::wildcard_match("*.png", "sprite.png.txt"));
+        assert!(!super::wildcard_match("sprite_?.png", "sprite_10.png"));
+    }
+
+    #[test]
+    fn output_path() {
+        let input = Path::new("sprites/hero.png");
+        assert_eq!(
+            super::output_path("out/{stem}_chars.{ext}", input, 3),
+            PathBuf::from("out/hero_chars.png")
+        );
+        assert_eq!(
+            super::output_path("{index}-{name}.txt", input, 3),
+            PathBuf::from("3-hero.png.txt")
+        );
+    }
+
+    #[test]
+    fn run_parallel() {
+        let items = (0..100).collect::<Vec<u32>>();
+        let results = super::run_parallel(&items, 4, |index, item| {
+            Ok::<u32, io::Error>(index as u32 + item)
+        });
+        assert_eq!(
+            results
+                .into_iter()
+                .map(Result::unwrap)
+                .collect::<Vec<u32>>(),
+            (0..100).map(|item| item * 2).collect::<Vec<u32>>()
+        );
+
+        let results = super::run_parallel(&items, 4, |_, item| {
+            if *item == 50 {
+                panic!("broken item");
+            }
+            Ok::<u32, io::Error>(*item)
+        });
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+        assert_eq!(
+            results[50].as_ref().unwrap_err().to_string(),
+            "panicked: broken item"
+        );
+    }
+}
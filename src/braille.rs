@@ -0,0 +1,156 @@
+use image::{DynamicImage, GrayImage, Rgba, RgbaImage};
+use imageproc::drawing::draw_filled_circle_mut;
+
+use crate::{
+    char_grid::{Cell, CharGrid},
+    char_mapper::{map_image, CellSample, CharMapper},
+    dither::Dither,
+};
+
+pub const BRAILLE_BLANK: u32 = 0x2800;
+pub const BRAILLE_CELL_WIDTH: u32 = 2;
+pub const BRAILLE_CELL_HEIGHT: u32 = 4;
+///Bit of each dot in a braille char, indexed by `[y][x]` inside its 2x4 cell.
+const DOT_BITS: [[u32; BRAILLE_CELL_WIDTH as usize]; BRAILLE_CELL_HEIGHT as usize] =
+    [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+pub trait AsBraille {
+    ///Packs every 2x4 pixel block into one braille char.
+    ///A dot is raised for each pixel that is at least as bright as `threshold`.
+    ///When dithering, `threshold` shifts the brightness of the image before it's dithered instead.
+    ///`inverted` raises dots for dark pixels instead, for dark dots on a light background.
+    fn as_braille(&self, threshold: u8, inverted: bool, dither: Option<Dither>) -> CharGrid;
+}
+
+///Raises a dot for each pixel of a 2x4 block that is at least as bright as `threshold`.
+pub struct BrailleMapper {
+    pub threshold: u8,
+    ///Inverts every pixel before comparing it to `threshold`, raising dots for dark pixels.
+    pub inverted: bool,
+}
+
+impl CharMapper for BrailleMapper {
+    fn cell_size(&self) -> (u32, u32) {
+        (BRAILLE_CELL_WIDTH, BRAILLE_CELL_HEIGHT)
+    }
+
+    fn map_cell(&self, sample: &CellSample) -> Cell {
+        let mut bits = 0u32;
+        for y in 0..sample.height {
+            for x in 0..sample.width {
+                let luma = if self.inverted {
+                    u8::MAX - sample.luma(x, y)
+                } else {
+                    sample.luma(x, y)
+                };
+                if luma >= self.threshold {
+                    bits |= DOT_BITS[y as usize][x as usize];
+                }
+            }
+        }
+        Cell::new(
+            char::from_u32(BRAILLE_BLANK + bits).unwrap(),
+            sample.mean_luma(),
+        )
+    }
+}
+
+impl AsBraille for GrayImage {
+    fn as_braille(&self, threshold: u8, inverted: bool, dither: Option<Dither>) -> CharGrid {
+        match dither {
+            Some(dither) => {
+                let mut image = self.clone();
+                if inverted {
+                    image::imageops::invert(&mut image);
+                }
+                let image = image::imageops::brighten(&image, 128 - threshold as i32);
+                let dots = dither.apply(&image, &[u8::MIN, u8::MAX]);
+                map_image(
+                    &DynamicImage::ImageLuma8(dots),
+                    &BrailleMapper {
+                        threshold: u8::MAX,
+                        inverted: false,
+                    },
+                )
+            }
+            None => map_image(
+                &DynamicImage::ImageLuma8(self.clone()),
+                &BrailleMapper {
+                    threshold,
+                    inverted,
+                },
+            ),
+        }
+    }
+}
+
+impl AsBraille for DynamicImage {
+    fn as_braille(&self, threshold: u8, inverted: bool, dither: Option<Dither>) -> CharGrid {
+        self.to_luma8().as_braille(threshold, inverted, dither)
+    }
+}
+
+pub fn is_braille(char: char) -> bool {
+    (BRAILLE_BLANK..BRAILLE_BLANK + 0x100).contains(&(char as u32))
+}
+
+///Draws the raised dots of a braille char as circles filling the cell at `origin`,
+///so `font` doesn't need to contain braille glyphs.
+pub fn draw_braille_dots(
+    image: &mut RgbaImage,
+    char: char,
+    origin: (f32, f32),
+    cell_size: (f32, f32),
+    color: Rgba<u8>,
+) {
+    let dot_width = cell_size.0 / BRAILLE_CELL_WIDTH as f32;
+    let dot_height = cell_size.1 / BRAILLE_CELL_HEIGHT as f32;
+    let radius = ((dot_width.min(dot_height) * 0.35) as i32).max(1);
+    let bits = char as u32 - BRAILLE_BLANK;
+    for (dy, dot_row) in DOT_BITS.iter().enumerate() {
+        for (dx, bit) in dot_row.iter().enumerate() {
+            if bits & bit == 0 {
+                continue;
+            }
+            let center = (
+                (origin.0 + (dx as f32 + 0.5) * dot_width) as i32,
+                (origin.1 + (dy as f32 + 0.5) * dot_height) as i32,
+            );
+            draw_filled_circle_mut(image, center, radius, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod braille_tests {
+    use image::Luma;
+
+    use super::*;
+
+    #[test]
+    fn as_braille() {
+        let mut image = GrayImage::new(4, 4);
+        image.put_pixel(0, 0, Luma([255]));
+        image.put_pixel(3, 3, Luma([255]));
+        assert_eq!(
+            image.as_braille(128, false, None).to_string(),
+            "\u{2801}\u{2880}\n"
+        );
+        assert_eq!(
+            image.as_braille(128, true, None).to_string(),
+            "\u{28fe}\u{287f}\n"
+        );
+    }
+
+    #[test]
+    fn as_braille_dither() {
+        let image = GrayImage::from_pixel(8, 8, Luma([128]));
+        let dots = image
+            .as_braille(128, false, Some(Dither::FloydSteinberg))
+            .cells()
+            .iter()
+            .map(|cell| (cell.char as u32 - BRAILLE_BLANK).count_ones())
+            .sum::<u32>();
+        assert!((24..=40).contains(&dots));
+    }
+}
@@ -2,6 +2,11 @@ use std::ops::Index;
 
 use rusttype::{point, Font, Scale, ScaledGlyph};
 
+use crate::{
+    char_grid::Cell,
+    char_mapper::{CellSample, CharMapper},
+};
+
 pub const CHARS_LENGTH: usize = 95;
 pub const CHARS: [char; CHARS_LENGTH] = [
     ' ', '!', '"', '#', '$', '%', '&', '\'', '(', ')', '*', '+', ',', '-', '.', '/', '0', '1', '2',
@@ -10,51 +15,132 @@ pub const CHARS: [char; CHARS_LENGTH] = [
     'Y', 'Z', '[', '\\', ']', '^', '_', '`', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k',
     'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '{', '|', '}', '~',
 ];
-const FONT: &[u8] = include_bytes!("/home/joknavi/.local/share/fonts/RobotoMono-Regular.ttf");
+pub(crate) const FONT: &[u8] =
+    include_bytes!("/home/joknavi/.local/share/fonts/RobotoMono-Regular.ttf");
 const SCALE: f32 = 40.0;
 const COLOR: f32 = u8::MAX as f32;
 
+pub const LUT_LENGTH: usize = u8::MAX as usize + 1;
+
+#[derive(Clone)]
 pub struct BrightnessCharMap {
-    char_lut: [char; u8::MAX as usize],
+    brightnesses: Vec<(char, u8)>,
+    char_lut: [char; LUT_LENGTH],
+    ///The brightness whose char draws a pixel, indexed by the pixel's brightness.
+    curve: [u8; LUT_LENGTH],
+    cell_aspect: f32,
 }
 
 impl BrightnessCharMap {
-
     fn new() -> BrightnessCharMap {
-        let brightnesses_tuples = Self::get_brightness_tuples();
+        let font = Font::try_from_bytes(FONT).unwrap();
+        Self::from_font(&font, Scale::uniform(SCALE))
+    }
+
+    ///Calibrates the brightness of every char against `font` rendered at `scale`.
+    pub fn from_font(font: &Font, scale: Scale) -> BrightnessCharMap {
+        Self::from_chars(CHARS, font, scale)
+    }
+
+    ///Calibrates a map that only uses `chars`, measured against `font` rendered at `scale`.
+    ///Duplicate chars are ignored. An empty set of chars maps every brightness to a space.
+    pub fn from_chars(
+        chars: impl IntoIterator<Item = char>,
+        font: &Font,
+        scale: Scale,
+    ) -> BrightnessCharMap {
+        let brightnesses_tuples = Self::get_brightness_tuples(chars, font, scale);
         Self {
-            char_lut: Self::brightness_tuples_to_lut(brightnesses_tuples),
+            char_lut: Self::brightness_tuples_to_lut(&brightnesses_tuples),
+            brightnesses: brightnesses_tuples,
+            curve: identity_curve(),
+            cell_aspect: cell_aspect(font),
         }
     }
 
-    fn get_brightness_tuples() -> [(char, u8); CHARS_LENGTH] {
-        let mut brightnesses = [(' ', u8::MIN); CHARS.len()];
-        let font = Font::try_from_bytes(FONT).unwrap();
-        let scale = Scale::uniform(SCALE);
-        for (i, char) in CHARS.into_iter().enumerate() {
-            unsafe {
-                *brightnesses.get_unchecked_mut(i) = (
-                    char,
-                    Self::average_brightness(font.glyph(char).scaled(scale)),
-                );
+    ///Rebuilds a map from previously measured brightnesses and the lut that was built from them.
+    pub fn from_parts(
+        brightnesses: Vec<(char, u8)>,
+        char_lut: [char; LUT_LENGTH],
+        cell_aspect: f32,
+    ) -> BrightnessCharMap {
+        Self {
+            brightnesses,
+            char_lut,
+            curve: identity_curve(),
+            cell_aspect,
+        }
+    }
+
+    ///Overrides the cell aspect measured from the calibration font.
+    pub fn with_cell_aspect(mut self, cell_aspect: f32) -> BrightnessCharMap {
+        self.cell_aspect = cell_aspect;
+        self
+    }
+
+    ///Looks up every pixel's brightness in `curve` before picking its char,
+    ///see [`crate::transfer::transfer_lut`].
+    pub fn with_curve(mut self, curve: [u8; LUT_LENGTH]) -> BrightnessCharMap {
+        self.curve = curve;
+        self
+    }
+
+    ///The brightness whose char draws a pixel, indexed by the pixel's brightness.
+    pub fn curve(&self) -> &[u8; LUT_LENGTH] {
+        &self.curve
+    }
+
+    ///The brightness of the darkest and the brightest char.
+    pub fn brightness_range(&self) -> (u8, u8) {
+        let brightnesses = self.brightnesses.iter().map(|(_, brightness)| *brightness);
+        match (brightnesses.clone().min(), brightnesses.max()) {
+            (Some(low), Some(high)) => (low, high),
+            _ => (u8::MIN, u8::MAX),
+        }
+    }
+
+    ///Maps dark pixels to dense chars and light pixels to sparse ones, for dark chars on a
+    ///light background. Every char's brightness becomes how bright it looks there.
+    pub fn inverted(mut self) -> BrightnessCharMap {
+        for (_, brightness) in &mut self.brightnesses {
+            *brightness = u8::MAX - *brightness;
+        }
+        self.char_lut.reverse();
+        self
+    }
+
+    ///How many times taller than wide a char is in the calibration font.
+    pub fn cell_aspect(&self) -> f32 {
+        self.cell_aspect
+    }
+
+    fn get_brightness_tuples(
+        chars: impl IntoIterator<Item = char>,
+        font: &Font,
+        scale: Scale,
+    ) -> Vec<(char, u8)> {
+        let mut brightnesses: Vec<(char, u8)> = Vec::with_capacity(CHARS_LENGTH);
+        for char in chars {
+            if brightnesses.iter().any(|(seen, _)| *seen == char) {
+                continue;
             }
+            brightnesses.push((
+                char,
+                Self::average_brightness(font.glyph(char).scaled(scale)),
+            ));
         }
         brightnesses
     }
 
     fn average_brightness(glyph: ScaledGlyph) -> u8 {
-        let mut buffer = [u32::MIN; 32];
-        let total_pixels = Self::glyph_width(&glyph) * SCALE;
-        glyph.positioned(point(0.0, 0.0)).draw(|x, y, v| {
+        let total_pixels = Self::glyph_width(&glyph) * glyph.scale().y;
+        let mut brightness = 0u32;
+        glyph.positioned(point(0.0, 0.0)).draw(|_, _, v| {
             if v > 0.5 {
-                buffer[y as usize] |= 1 << x;
+                brightness += 1;
             }
         });
-        let brightness = buffer
-            .into_iter()
-            .map(|row| row.count_ones() as u16)
-            .sum::<u16>();
-        ((brightness as f32 * COLOR) / total_pixels) as u8
+        ((brightness as f32 * COLOR) / total_pixels).min(COLOR) as u8
     }
 
     fn glyph_width(glyph: &ScaledGlyph) -> f32 {
@@ -62,16 +148,17 @@ impl BrightnessCharMap {
         h_metrics.advance_width + h_metrics.left_side_bearing
     }
 
-    fn brightness_tuples_to_lut(tuples: [(char, u8); CHARS_LENGTH]) -> [char; u8::MAX as usize] {
-        let mut lut = ['\x00'; u8::MAX as usize];
-        let mut offset = [u8::MAX; u8::MAX as usize];
-    
-        for (char, brightness) in tuples {
+    fn brightness_tuples_to_lut(tuples: &[(char, u8)]) -> [char; LUT_LENGTH] {
+        let mut lut = [' '; LUT_LENGTH];
+        let mut offset = [u8::MAX; LUT_LENGTH];
+
+        for &(char, brightness) in tuples {
             lut[brightness as usize] = char;
             offset[brightness as usize] = 0u8;
-    
-            let mut i = 0usize;
-            while i < brightness as usize {
+
+            let mut i = brightness as usize;
+            while i > 0 {
+                i -= 1;
                 let new_offset = brightness - i as u8;
                 if offset[i] < new_offset {
                     break;
@@ -80,12 +167,10 @@ impl BrightnessCharMap {
                     *offset.get_unchecked_mut(i) = new_offset;
                     *lut.get_unchecked_mut(i) = char;
                 }
-                
-                i += 1;
             }
-    
-            let mut i = (brightness + 1) as usize;
-            while i < u8::MAX as usize {
+
+            let mut i = brightness as usize + 1;
+            while i < LUT_LENGTH {
                 let new_offset = i as u8 - brightness;
                 if offset[i] < new_offset {
                     break;
@@ -97,15 +182,59 @@ impl BrightnessCharMap {
                 i += 1;
             }
         }
-    
+
         lut
     }
 
+    ///Every char in the map together with its measured brightness.
+    pub fn brightnesses(&self) -> &[(char, u8)] {
+        &self.brightnesses
+    }
+
+    ///The char that draws a pixel of `brightness`, after looking it up in the curve.
+    pub fn char_for(&self, brightness: u8) -> char {
+        self.char_lut[self.curve[brightness as usize] as usize]
+    }
+
+    ///The char used for every brightness, indexed by brightness.
+    ///This is the raw lut, use [`Self::char_for`] to apply the curve.
+    pub fn lut(&self) -> &[char; LUT_LENGTH] {
+        &self.char_lut
+    }
+
+    ///Looks `brightness` up in the raw lut, ignoring the curve.
     ///# Safety
-    ///Can't fail if self.char_lut is length 255 or longer.
+    ///Can't fail if self.char_lut is length 256 or longer.
     ///Which it always is.
-    pub unsafe fn get_unchecked(&self, brigthness: u8) -> char {
-        *self.char_lut.get_unchecked(brigthness as usize)
+    pub unsafe fn get_unchecked(&self, brightness: u8) -> char {
+        *self.char_lut.get_unchecked(brightness as usize)
+    }
+}
+
+fn identity_curve() -> [u8; LUT_LENGTH] {
+    std::array::from_fn(|brightness| brightness as u8)
+}
+
+///How many times taller than wide a char cell of `font` is, using its line height and advance width.
+pub fn cell_aspect(font: &Font) -> f32 {
+    let scale = Scale::uniform(SCALE);
+    let v_metrics = font.v_metrics(scale);
+    let advance_width = font.glyph(' ').scaled(scale).h_metrics().advance_width;
+    (v_metrics.ascent - v_metrics.descent + v_metrics.line_gap) / advance_width
+}
+
+impl CharMapper for BrightnessCharMap {
+    fn cell_size(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn height_scale(&self) -> f32 {
+        1.0 / self.cell_aspect
+    }
+
+    fn map_cell(&self, sample: &CellSample) -> Cell {
+        let brightness = sample.mean_luma();
+        Cell::new(self.char_for(brightness), brightness)
     }
 }
 
@@ -115,6 +244,7 @@ impl Default for BrightnessCharMap {
     }
 }
 
+///Indexes the raw lut, ignoring the curve like [`BrightnessCharMap::lut`].
 impl Index<usize> for BrightnessCharMap {
     type Output = char;
     fn index(&self, index: usize) -> &Self::Output {
@@ -130,4 +260,31 @@ mod brightness_char_map_tests {
     fn get_brightness_tuples() {
         let _char_map = BrightnessCharMap::default();
     }
+
+    #[test]
+    fn from_font() {
+        let font = Font::try_from_bytes(FONT).unwrap();
+        let char_map = BrightnessCharMap::from_font(&font, Scale::uniform(12.0));
+        assert_ne!(char_map[254], ' ');
+        assert!((1.5..2.5).contains(&char_map.cell_aspect()));
+    }
+
+    #[test]
+    fn from_chars() {
+        let font = Font::try_from_bytes(FONT).unwrap();
+        let char_map =
+            BrightnessCharMap::from_chars(" .:-=+*#%@".chars(), &font, Scale::uniform(SCALE));
+        assert_eq!(char_map[0], ' ');
+        assert_eq!(char_map[255], '@');
+
+        let inverted = char_map.clone().inverted();
+        assert_eq!(inverted[0], '@');
+        assert_eq!(inverted[255], ' ');
+        assert!(inverted.brightnesses().contains(&(' ', u8::MAX)));
+        assert_eq!(inverted.brightness_range().1, u8::MAX);
+
+        let curved = char_map.with_curve([u8::MAX; LUT_LENGTH]);
+        assert_eq!(curved.char_for(0), '@');
+        assert_eq!(curved[0], ' ');
+    }
 }
@@ -0,0 +1,398 @@
+use std::fmt::{self, Display};
+
+use image::{imageops::FilterType, DynamicImage, Pixel, Rgb, Rgba, RgbaImage};
+use rusttype::{Font, Scale};
+
+use crate::{
+    as_chars::{as_chars_image, GridLayout},
+    color::{ColorMode, ANSI_RESET},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub char: char,
+    ///Brightness of the part of the source image the char replaces, 0 when unknown.
+    pub brightness: u8,
+    pub foreground: Option<Rgb<u8>>,
+    pub background: Option<Rgb<u8>>,
+}
+
+impl Cell {
+    pub fn new(char: char, brightness: u8) -> Cell {
+        Self {
+            char,
+            brightness,
+            foreground: None,
+            background: None,
+        }
+    }
+
+    pub fn with_foreground(mut self, color: Rgb<u8>) -> Cell {
+        self.foreground = Some(color);
+        self
+    }
+
+    pub fn with_background(mut self, color: Rgb<u8>) -> Cell {
+        self.background = Some(color);
+        self
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self::new(' ', u8::MIN)
+    }
+}
+
+///The chars an image was converted to, laid out row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharGrid {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl CharGrid {
+    ///# Panics
+    ///Panics if `cells` doesn't hold exactly `width * height` cells.
+    pub fn new(width: usize, height: usize, cells: Vec<Cell>) -> CharGrid {
+        assert_eq!(cells.len(), width * height, "cells don't fill the grid");
+        Self {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    ///Reads plain char art, padding shorter lines with spaces.
+    pub fn from_text(text: &str) -> CharGrid {
+        let lines = text.lines().collect::<Vec<&str>>();
+        let width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut cells = Vec::with_capacity(width * lines.len());
+        for line in &lines {
+            let length = cells.len();
+            cells.extend(line.chars().map(|char| Cell::new(char, u8::MIN)));
+            cells.resize(length + width, Cell::default());
+        }
+        Self::new(width, lines.len(), cells)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&Cell> {
+        if x >= self.width {
+            return None;
+        }
+        self.cells.get(y * self.width + x)
+    }
+
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.cells.chunks(self.width.max(1)).take(self.height)
+    }
+
+    ///Every row as a plain string, without any colour.
+    pub fn lines(&self) -> impl Iterator<Item = String> + '_ {
+        self.rows()
+            .map(|row| row.iter().map(|cell| cell.char).collect())
+    }
+
+    ///Colours every cell with the average colour of the part of `image` it covers.
+    ///For grids that weren't mapped cell by cell, such as dithered ones.
+    pub fn color_from(&mut self, image: &DynamicImage) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        let colors = image
+            .resize_exact(self.width as u32, self.height as u32, FilterType::Triangle)
+            .into_rgb8();
+        for (cell, color) in self.cells.iter_mut().zip(colors.pixels()) {
+            cell.foreground = Some(*color);
+        }
+    }
+
+    ///Gives every coloured cell a background of its foreground colour darkened by `amount`.
+    pub fn fill_backgrounds(&mut self, amount: i32) {
+        for cell in &mut self.cells {
+            if let Some(color) = cell.foreground {
+                cell.background =
+                    Some(color.map(|channel| (channel as i32 - amount).clamp(0, 255) as u8));
+            }
+        }
+    }
+
+    ///Renders the grid with ANSI escapes for the colours of every cell.
+    ///Runs of cells with the same colours share a single escape.
+    pub fn to_ansi(&self, mode: ColorMode) -> String {
+        let mut ansi = String::with_capacity(self.cells.len() * 4);
+        for row in self.rows() {
+            let mut current = (None, None);
+            for cell in row {
+                let escape = (
+                    cell.foreground.and_then(|color| mode.foreground(color)),
+                    cell.background.and_then(|color| mode.background(color)),
+                );
+                if escape != current {
+                    let clears_color = (current.0.is_some() && escape.0.is_none())
+                        || (current.1.is_some() && escape.1.is_none());
+                    if clears_color {
+                        ansi.push_str(ANSI_RESET);
+                    }
+                    ansi.push_str(escape.0.as_deref().unwrap_or_default());
+                    ansi.push_str(escape.1.as_deref().unwrap_or_default());
+                    current = escape;
+                }
+                ansi.push(cell.char);
+            }
+            if current != (None, None) {
+                ansi.push_str(ANSI_RESET);
+            }
+            ansi.push('\n');
+        }
+        ansi
+    }
+
+    ///Renders the grid as a `<pre>` block, wrapping runs of coloured cells in `<span>`s.
+    pub fn to_html(&self) -> String {
+        let mut html = String::from("<pre>");
+        for row in self.rows() {
+            let mut current = None;
+            for cell in row {
+                let style = html_style(cell);
+                if style != current {
+                    if current.is_some() {
+                        html.push_str("</span>");
+                    }
+                    if let Some(style) = &style {
+                        html.push_str(&format!("<span style=\"{}\">", style));
+                    }
+                    current = style;
+                }
+                match cell.char {
+                    '&' => html.push_str("&amp;"),
+                    '<' => html.push_str("&lt;"),
+                    '>' => html.push_str("&gt;"),
+                    '"' => html.push_str("&quot;"),
+                    char => html.push(char),
+                }
+            }
+            if current.is_some() {
+                html.push_str("</span>");
+            }
+            html.push('\n');
+        }
+        html.push_str("</pre>");
+        html
+    }
+
+    ///Wraps [`CharGrid::to_html`] in a standalone page drawn in `font_family`, with a slider to zoom in and out.
+    pub fn to_html_page(
+        &self,
+        font_family: &str,
+        foreground: Rgba<u8>,
+        background: Rgba<u8>,
+    ) -> String {
+        let hex = |color: Rgba<u8>| format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2]);
+        let font_family = sanitize_font_family(font_family);
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>char art</title>
+<style>
+body {{ background: {}; color: {}; margin: 1em; }}
+pre {{ font-family: '{}', monospace; line-height: 1; }}
+</style>
+</head>
+<body>
+<label>Zoom <input id="zoom" type="range" min="25" max="400" value="100"></label>
+{}
+<script>
+const zoom = document.getElementById("zoom");
+const art = document.querySelector("pre");
+zoom.addEventListener("input", () => art.style.fontSize = zoom.value / 100 + "em");
+</script>
+</body>
+</html>
+"#,
+            hex(background),
+            hex(foreground),
+            font_family,
+            self.to_html()
+        )
+    }
+
+    pub fn to_image(
+        &self,
+        font: &Font,
+        scale: Scale,
+        layout: GridLayout,
+        foreground: Rgba<u8>,
+        background: Rgba<u8>,
+    ) -> RgbaImage {
+        as_chars_image(self, font, scale, layout, foreground, background)
+    }
+}
+
+///Replaces every tab with the spaces up to the next multiple of `tab_width` columns.
+pub fn expand_tabs(text: &str, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let mut expanded = String::with_capacity(text.len());
+    let mut column = 0;
+    for char in text.chars() {
+        match char {
+            '\t' => {
+                let spaces = tab_width - column % tab_width;
+                expanded.push_str(&" ".repeat(spaces));
+                column += spaces;
+            }
+            '\n' => {
+                expanded.push(char);
+                column = 0;
+            }
+            char => {
+                expanded.push(char);
+                column += 1;
+            }
+        }
+    }
+    expanded
+}
+
+///Drops every char that could break out of a quoted CSS or SVG font family name.
+pub fn sanitize_font_family(font_family: &str) -> String {
+    font_family
+        .chars()
+        .filter(|char| char.is_alphanumeric() || matches!(char, ' ' | '-' | '_'))
+        .collect()
+}
+
+fn html_style(cell: &Cell) -> Option<String> {
+    let hex = |color: Rgb<u8>| format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2]);
+    match (cell.foreground, cell.background) {
+        (None, None) => None,
+        (Some(foreground), None) => Some(format!("color:{}", hex(foreground))),
+        (None, Some(background)) => Some(format!("background:{}", hex(background))),
+        (Some(foreground), Some(background)) => Some(format!(
+            "color:{};background:{}",
+            hex(foreground),
+            hex(background)
+        )),
+    }
+}
+
+impl Display for CharGrid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in self.lines() {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<CharGrid> for String {
+    fn from(grid: CharGrid) -> Self {
+        grid.to_string()
+    }
+}
+
+impl From<&CharGrid> for String {
+    fn from(grid: &CharGrid) -> Self {
+        grid.to_string()
+    }
+}
+
+#[cfg(test)]
+mod char_grid_tests {
+    use super::*;
+
+    #[test]
+    fn from_text() {
+        let grid = CharGrid::from_text("ab\nc\n");
+        assert_eq!((grid.width(), grid.height()), (2, 2));
+        assert_eq!(grid.get(1, 1).unwrap().char, ' ');
+        assert_eq!(grid.to_string(), "ab\nc \n");
+    }
+
+    #[test]
+    fn expand_tabs() {
+        assert_eq!(super::expand_tabs("a\tb\n\tc", 4), "a   b\n    c");
+        assert_eq!(super::expand_tabs("abcd\te", 4), "abcd    e");
+    }
+
+    #[test]
+    fn to_html_page() {
+        let page = CharGrid::from_text("a<").to_html_page(
+            "Fira Code'; }",
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+        );
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("font-family: 'Fira Code ', monospace;"));
+        assert!(page.contains("background: #ffffff; color: #000000;"));
+        assert!(page.contains("<pre>a&lt;\n</pre>"));
+    }
+
+    #[test]
+    fn color_from() {
+        let mut grid = CharGrid::from_text("ab");
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(2, 1, |x, _| {
+            Rgb([255 * (1 - x as u8), 0, 255 * x as u8])
+        }));
+        grid.color_from(&image);
+        assert_eq!(grid.cells()[0].foreground, Some(Rgb([255, 0, 0])));
+        assert_eq!(grid.cells()[1].foreground, Some(Rgb([0, 0, 255])));
+    }
+
+    #[test]
+    fn fill_backgrounds() {
+        let mut grid = CharGrid::new(
+            2,
+            1,
+            vec![
+                Cell::new('a', 10).with_foreground(Rgb([200, 100, 50])),
+                Cell::new('b', 10),
+            ],
+        );
+        grid.fill_backgrounds(60);
+        assert_eq!(grid.cells()[0].background, Some(Rgb([140, 40, 0])));
+        assert_eq!(grid.cells()[1].background, None);
+    }
+
+    #[test]
+    fn to_ansi_and_html() {
+        let red = Rgb([255, 0, 0]);
+        let grid = CharGrid::new(
+            3,
+            1,
+            vec![
+                Cell::new('<', 10).with_foreground(red),
+                Cell::new('b', 10).with_foreground(red),
+                Cell::new('c', 10),
+            ],
+        );
+        assert_eq!(
+            grid.to_ansi(ColorMode::TrueColor),
+            "\x1b[38;2;255;0;0m<b\x1b[0mc\n"
+        );
+        assert_eq!(grid.to_ansi(ColorMode::None), "<bc\n");
+        assert_eq!(
+            grid.to_html(),
+            "<pre><span style=\"color:#ff0000\">&lt;b</span>c\n</pre>"
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::brightness_char_map::{BrightnessCharMap, LUT_LENGTH};
+
+pub const CHAR_MAP_FILE_VERSION: u32 = 1;
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+///A calibrated [`BrightnessCharMap`] stored as JSON, so the font doesn't need to be measured again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CharMapFile {
+    pub version: u32,
+    ///FNV-1a hash of the font file the map was calibrated against.
+    pub font_hash: String,
+    pub scale: f32,
+    ///How many times taller than wide a char is in the font.
+    #[serde(default = "default_cell_aspect")]
+    pub cell_aspect: f32,
+    pub brightnesses: Vec<(char, u8)>,
+    ///The char used for every brightness, indexed by brightness.
+    pub lut: String,
+}
+
+impl CharMapFile {
+    pub fn new(char_map: &BrightnessCharMap, font: &[u8], scale: f32) -> CharMapFile {
+        Self {
+            version: CHAR_MAP_FILE_VERSION,
+            font_hash: font_hash(font),
+            scale,
+            cell_aspect: char_map.cell_aspect(),
+            brightnesses: char_map.brightnesses().to_vec(),
+            lut: char_map.lut().iter().collect(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<CharMapFile> {
+        let file: CharMapFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if file.version != CHAR_MAP_FILE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported char map version {}", file.version),
+            ));
+        }
+        Ok(file)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    ///Whether the map was calibrated against `font`.
+    pub fn matches_font(&self, font: &[u8]) -> bool {
+        self.font_hash == font_hash(font)
+    }
+
+    pub fn to_char_map(&self) -> io::Result<BrightnessCharMap> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if self.brightnesses.is_empty() {
+            return Err(invalid("char map has no brightnesses".to_string()));
+        }
+        let lut: [char; LUT_LENGTH] =
+            self.lut
+                .chars()
+                .collect::<Vec<char>>()
+                .try_into()
+                .map_err(|lut: Vec<char>| {
+                    invalid(format!(
+                        "char map lut has {} entries instead of {}",
+                        lut.len(),
+                        LUT_LENGTH
+                    ))
+                })?;
+        if let Some(char) = lut
+            .iter()
+            .find(|char| !self.brightnesses.iter().any(|(known, _)| known == *char))
+        {
+            return Err(invalid(format!(
+                "char map lut uses `{}`, which has no brightness",
+                char
+            )));
+        }
+        Ok(BrightnessCharMap::from_parts(
+            self.brightnesses.clone(),
+            lut,
+            self.cell_aspect,
+        ))
+    }
+}
+
+fn default_cell_aspect() -> f32 {
+    2.0
+}
+
+pub fn font_hash(font: &[u8]) -> String {
+    let hash = font.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod char_map_file_tests {
+    use super::*;
+    use crate::brightness_char_map::FONT;
+
+    #[test]
+    fn save_and_load() {
+        let char_map = BrightnessCharMap::default();
+        let file = CharMapFile::new(&char_map, FONT, 40.0);
+        let path = std::env::temp_dir().join("char_art_char_map_file_test.json");
+        file.save(&path).unwrap();
+
+        let loaded = CharMapFile::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, file);
+        assert_eq!(loaded.to_char_map().unwrap().lut(), char_map.lut());
+        assert!(loaded.matches_font(FONT));
+        assert!(!loaded.matches_font(b"another font"));
+
+        let mut broken = file.clone();
+        broken.brightnesses.retain(|(char, _)| *char != '@');
+        assert!(broken.to_char_map().is_err());
+        broken.brightnesses.clear();
+        assert!(broken.to_char_map().is_err());
+    }
+
+    #[test]
+    fn font_hash() {
+        assert_eq!(super::font_hash(b""), "cbf29ce484222325");
+        assert_eq!(super::font_hash(b"a"), "af63dc4c8601ec8c");
+    }
+}
@@ -0,0 +1,171 @@
+use image::{imageops::FilterType, DynamicImage, Rgb};
+
+use crate::char_grid::{Cell, CharGrid};
+
+///The pixels of the source image that end up in a single cell, row by row.
+///Cells along the right and bottom edge of the image can be smaller than [`CharMapper::cell_size`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellSample {
+    pub width: u32,
+    pub height: u32,
+    pub luma: Vec<u8>,
+    pub rgb: Vec<Rgb<u8>>,
+}
+
+impl CellSample {
+    pub fn luma(&self, x: u32, y: u32) -> u8 {
+        self.luma[(y * self.width + x) as usize]
+    }
+
+    pub fn rgb(&self, x: u32, y: u32) -> Rgb<u8> {
+        self.rgb[(y * self.width + x) as usize]
+    }
+
+    pub fn mean_luma(&self) -> u8 {
+        let sum = self.luma.iter().map(|luma| *luma as u32).sum::<u32>();
+        (sum / self.luma.len().max(1) as u32) as u8
+    }
+
+    pub fn mean_rgb(&self) -> Rgb<u8> {
+        let mut sum = [0u32; 3];
+        for pixel in &self.rgb {
+            for (sum, channel) in sum.iter_mut().zip(pixel.0) {
+                *sum += channel as u32;
+            }
+        }
+        Rgb(sum.map(|sum| (sum / self.rgb.len().max(1) as u32) as u8))
+    }
+}
+
+///Turns blocks of pixels into styled chars.
+///Implement this to plug a custom renderer into [`crate::as_chars::AsChars::as_mapped_chars`].
+pub trait CharMapper {
+    ///How many pixels wide and tall the block behind a single cell is.
+    fn cell_size(&self) -> (u32, u32);
+
+    ///How much the image is stretched vertically before it's cut into cells.
+    ///Mappers with one pixel per cell use this to make up for chars being taller than wide.
+    fn height_scale(&self) -> f32 {
+        1.0
+    }
+
+    ///Whether the cut off blocks along the right and bottom edge still become cells.
+    ///When `false` they're dropped, so every cell covers a whole block.
+    fn partial_cells(&self) -> bool {
+        true
+    }
+
+    fn map_cell(&self, sample: &CellSample) -> Cell;
+}
+
+impl<M: CharMapper + ?Sized> CharMapper for &M {
+    fn cell_size(&self) -> (u32, u32) {
+        (**self).cell_size()
+    }
+
+    fn height_scale(&self) -> f32 {
+        (**self).height_scale()
+    }
+
+    fn partial_cells(&self) -> bool {
+        (**self).partial_cells()
+    }
+
+    fn map_cell(&self, sample: &CellSample) -> Cell {
+        (**self).map_cell(sample)
+    }
+}
+
+///Wraps another mapper and colours each of its cells with the average colour of its block.
+pub struct Colored<M>(pub M);
+
+impl<M: CharMapper> CharMapper for Colored<M> {
+    fn cell_size(&self) -> (u32, u32) {
+        self.0.cell_size()
+    }
+
+    fn height_scale(&self) -> f32 {
+        self.0.height_scale()
+    }
+
+    fn partial_cells(&self) -> bool {
+        self.0.partial_cells()
+    }
+
+    fn map_cell(&self, sample: &CellSample) -> Cell {
+        self.0.map_cell(sample).with_foreground(sample.mean_rgb())
+    }
+}
+
+///Stretches `image` by the mapper's height scale, cuts it into cells and maps every one of them.
+pub fn map_image(image: &DynamicImage, mapper: &dyn CharMapper) -> CharGrid {
+    let height = ((image.height() as f32 * mapper.height_scale()).round() as u32).max(1);
+    let image = if height == image.height() {
+        image.clone()
+    } else {
+        image.resize_exact(image.width(), height, FilterType::Lanczos3)
+    };
+    let luma = image.to_luma8();
+    let rgb = image.to_rgb8();
+
+    let (cell_width, cell_height) = mapper.cell_size();
+    let (columns, rows) = if mapper.partial_cells() {
+        (
+            image.width().div_ceil(cell_width),
+            image.height().div_ceil(cell_height),
+        )
+    } else {
+        (image.width() / cell_width, image.height() / cell_height)
+    };
+    let mut cells = Vec::with_capacity((columns * rows) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            let left = column * cell_width;
+            let top = row * cell_height;
+            let width = cell_width.min(image.width() - left);
+            let height = cell_height.min(image.height() - top);
+            let mut sample = CellSample {
+                width,
+                height,
+                luma: Vec::with_capacity((width * height) as usize),
+                rgb: Vec::with_capacity((width * height) as usize),
+            };
+            for y in top..top + height {
+                for x in left..left + width {
+                    sample.luma.push(luma.get_pixel(x, y).0[0]);
+                    sample.rgb.push(*rgb.get_pixel(x, y));
+                }
+            }
+            cells.push(mapper.map_cell(&sample));
+        }
+    }
+    CharGrid::new(columns as usize, rows as usize, cells)
+}
+
+#[cfg(test)]
+mod char_mapper_tests {
+    use image::RgbImage;
+
+    use super::*;
+
+    struct Hash;
+
+    impl CharMapper for Hash {
+        fn cell_size(&self) -> (u32, u32) {
+            (2, 2)
+        }
+
+        fn map_cell(&self, sample: &CellSample) -> Cell {
+            Cell::new('#', sample.mean_luma())
+        }
+    }
+
+    #[test]
+    fn map_image() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(5, 4, Rgb([0, 0, 255])));
+        let grid = super::map_image(&image, &Colored(Hash));
+        assert_eq!((grid.width(), grid.height()), (3, 2));
+        assert_eq!(grid.to_string(), "###\n###\n");
+        assert_eq!(grid.get(2, 1).unwrap().foreground, Some(Rgb([0, 0, 255])));
+    }
+}
@@ -0,0 +1,223 @@
+use std::str::FromStr;
+
+use image::{DynamicImage, Rgb, RgbImage, Rgba};
+
+use crate::{
+    brightness_char_map::BrightnessCharMap,
+    char_grid::CharGrid,
+    char_mapper::{map_image, Colored},
+};
+
+pub const ANSI_RESET: &str = "\x1b[0m";
+///The xterm defaults for the 16 basic ANSI colours, in escape code order.
+const ANSI_16_COLORS: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+const ANSI_256_CUBE_STEPS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    None,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorMode {
+    ///Returns the escape sequence that sets the foreground to (the closest available match of) `color`.
+    pub fn foreground(&self, color: Rgb<u8>) -> Option<String> {
+        let [r, g, b] = color.0;
+        match self {
+            ColorMode::None => None,
+            ColorMode::Ansi16 => {
+                let index = closest_ansi_16(color);
+                let code = if index < 8 {
+                    30 + index
+                } else {
+                    90 + index - 8
+                };
+                Some(format!("\x1b[{}m", code))
+            }
+            ColorMode::Ansi256 => Some(format!("\x1b[38;5;{}m", closest_ansi_256(color))),
+            ColorMode::TrueColor => Some(format!("\x1b[38;2;{};{};{}m", r, g, b)),
+        }
+    }
+
+    ///Returns the escape sequence that sets the background to (the closest available match of) `color`.
+    pub fn background(&self, color: Rgb<u8>) -> Option<String> {
+        let [r, g, b] = color.0;
+        match self {
+            ColorMode::None => None,
+            ColorMode::Ansi16 => {
+                let index = closest_ansi_16(color);
+                let code = if index < 8 {
+                    40 + index
+                } else {
+                    100 + index - 8
+                };
+                Some(format!("\x1b[{}m", code))
+            }
+            ColorMode::Ansi256 => Some(format!("\x1b[48;5;{}m", closest_ansi_256(color))),
+            ColorMode::TrueColor => Some(format!("\x1b[48;2;{};{};{}m", r, g, b)),
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ColorMode::None),
+            "16" => Ok(ColorMode::Ansi16),
+            "256" => Ok(ColorMode::Ansi256),
+            "truecolor" => Ok(ColorMode::TrueColor),
+            _ => Err(format!(
+                "unknown colour mode `{}`, expected none, 16, 256 or truecolor",
+                s
+            )),
+        }
+    }
+}
+
+fn color_distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| (*a as i32 - *b as i32).pow(2) as u32)
+        .sum()
+}
+
+fn closest_ansi_16(color: Rgb<u8>) -> u8 {
+    (0..ANSI_16_COLORS.len())
+        .min_by_key(|index| color_distance(color.0, ANSI_16_COLORS[*index]))
+        .unwrap() as u8
+}
+
+fn closest_ansi_256(color: Rgb<u8>) -> u8 {
+    let closest_step = |channel: u8| {
+        (0..ANSI_256_CUBE_STEPS.len())
+            .min_by_key(|index| (ANSI_256_CUBE_STEPS[*index] as i32 - channel as i32).abs())
+            .unwrap()
+    };
+    let [r, g, b] = color.0.map(closest_step);
+    let cube = [
+        ANSI_256_CUBE_STEPS[r],
+        ANSI_256_CUBE_STEPS[g],
+        ANSI_256_CUBE_STEPS[b],
+    ];
+
+    let average = color.0.iter().map(|channel| *channel as u32).sum::<u32>() / 3;
+    let gray_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + gray_index * 10;
+
+    if color_distance(color.0, [gray_level; 3]) < color_distance(color.0, cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * r as u8 + 6 * g as u8 + b as u8
+    }
+}
+
+///Parses a colour given as `#rgb`, `#rrggbb` or `#rrggbbaa` (the `#` is optional), or as `black` or `white`.
+pub fn parse_color(color: &str) -> Result<Rgba<u8>, String> {
+    match color.to_ascii_lowercase().as_str() {
+        "black" => return Ok(Rgba([0, 0, 0, u8::MAX])),
+        "white" => return Ok(Rgba([u8::MAX, u8::MAX, u8::MAX, u8::MAX])),
+        _ => {}
+    }
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let invalid = || format!("invalid colour '{}', expected #rrggbb", color);
+    let digits = hex
+        .chars()
+        .map(|digit| digit.to_digit(16).map(|digit| digit as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    let channels = match digits.len() {
+        3 => digits.iter().map(|digit| digit * 17).collect::<Vec<u8>>(),
+        6 | 8 => digits
+            .chunks(2)
+            .map(|pair| pair[0] * 16 + pair[1])
+            .collect::<Vec<u8>>(),
+        _ => return Err(invalid()),
+    };
+    Ok(Rgba([
+        channels[0],
+        channels[1],
+        channels[2],
+        *channels.get(3).unwrap_or(&u8::MAX),
+    ]))
+}
+
+pub trait AsColoredChars {
+    ///Like [`crate::as_chars::AsChars::as_chars`], but keeps the colour of each pixel as the
+    ///foreground of its cell.
+    fn as_colored_chars(&self, char_map: &BrightnessCharMap) -> CharGrid;
+}
+
+impl AsColoredChars for RgbImage {
+    fn as_colored_chars(&self, char_map: &BrightnessCharMap) -> CharGrid {
+        map_image(&DynamicImage::ImageRgb8(self.clone()), &Colored(char_map))
+    }
+}
+
+impl AsColoredChars for DynamicImage {
+    fn as_colored_chars(&self, char_map: &BrightnessCharMap) -> CharGrid {
+        map_image(self, &Colored(char_map))
+    }
+}
+
+#[cfg(test)]
+mod color_tests {
+    use super::*;
+
+    #[test]
+    fn parse_color() {
+        assert_eq!(super::parse_color("#ff8000"), Ok(Rgba([255, 128, 0, 255])));
+        assert_eq!(super::parse_color("f80"), Ok(Rgba([255, 136, 0, 255])));
+        assert_eq!(super::parse_color("#00000080"), Ok(Rgba([0, 0, 0, 128])));
+        assert_eq!(super::parse_color("White"), Ok(Rgba([255, 255, 255, 255])));
+        assert!(super::parse_color("#12345").is_err());
+        assert!(super::parse_color("#gggggg").is_err());
+    }
+
+    #[test]
+    fn foreground() {
+        let red = Rgb([255, 0, 0]);
+        assert_eq!(ColorMode::None.foreground(red), None);
+        assert_eq!(ColorMode::Ansi16.foreground(red).unwrap(), "\x1b[91m");
+        assert_eq!(
+            ColorMode::Ansi256.foreground(red).unwrap(),
+            "\x1b[38;5;196m"
+        );
+        assert_eq!(
+            ColorMode::TrueColor.foreground(red).unwrap(),
+            "\x1b[38;2;255;0;0m"
+        );
+        assert_eq!(ColorMode::Ansi16.background(red).unwrap(), "\x1b[101m");
+        assert_eq!(closest_ansi_256(Rgb([128, 128, 128])), 244);
+    }
+
+    #[test]
+    fn as_colored_chars_merges_runs() {
+        let image = RgbImage::from_pixel(4, 2, Rgb([255, 0, 0]));
+        let chars = image
+            .as_colored_chars(&BrightnessCharMap::default())
+            .to_ansi(ColorMode::TrueColor);
+        assert_eq!(chars.matches("\x1b[38;2;").count(), 1);
+        assert!(chars.ends_with("\x1b[0m\n"));
+    }
+}
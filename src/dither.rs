@@ -0,0 +1,164 @@
+use std::str::FromStr;
+
+use image::{GrayImage, Luma};
+
+///Error diffusion kernels as `(dx, dy, weight)`, with the weights already divided by the kernel's total.
+const FLOYD_STEINBERG: [(i32, i32, f32); 4] = [
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+const ATKINSON: [(i32, i32, f32); 6] = [
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+const SIERRA: [(i32, i32, f32); 10] = [
+    (1, 0, 5.0 / 32.0),
+    (2, 0, 3.0 / 32.0),
+    (-2, 1, 2.0 / 32.0),
+    (-1, 1, 4.0 / 32.0),
+    (0, 1, 5.0 / 32.0),
+    (1, 1, 4.0 / 32.0),
+    (2, 1, 2.0 / 32.0),
+    (-1, 2, 2.0 / 32.0),
+    (0, 2, 3.0 / 32.0),
+    (1, 2, 2.0 / 32.0),
+];
+const BAYER_SIZE: usize = 4;
+const BAYER: [[u8; BAYER_SIZE]; BAYER_SIZE] =
+    [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    FloydSteinberg,
+    Atkinson,
+    Sierra,
+    Bayer,
+}
+
+impl FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "floyd-steinberg" => Ok(Dither::FloydSteinberg),
+            "atkinson" => Ok(Dither::Atkinson),
+            "sierra" => Ok(Dither::Sierra),
+            "bayer" => Ok(Dither::Bayer),
+            _ => Err(format!(
+                "unknown dither method `{}`, expected floyd-steinberg, atkinson, sierra or bayer",
+                s
+            )),
+        }
+    }
+}
+
+impl Dither {
+    ///Snaps every pixel of `image` to one of `levels`, spreading the rounding error over its neighbours.
+    ///`levels` doesn't need to be sorted and may contain duplicates.
+    ///Without any levels the image is returned unchanged.
+    pub fn apply(&self, image: &GrayImage, levels: &[u8]) -> GrayImage {
+        if levels.is_empty() {
+            return image.clone();
+        }
+        let mut levels = levels.to_vec();
+        levels.sort_unstable();
+        levels.dedup();
+
+        match self {
+            Dither::FloydSteinberg => diffuse(image, &levels, &FLOYD_STEINBERG),
+            Dither::Atkinson => diffuse(image, &levels, &ATKINSON),
+            Dither::Sierra => diffuse(image, &levels, &SIERRA),
+            Dither::Bayer => ordered(image, &levels),
+        }
+    }
+}
+
+fn closest_level(levels: &[u8], value: f32) -> u8 {
+    let index = levels.partition_point(|level| (*level as f32) < value);
+    match (index.checked_sub(1), levels.get(index)) {
+        (Some(below), Some(above)) if value - levels[below] as f32 <= *above as f32 - value => {
+            levels[below]
+        }
+        (_, Some(above)) => *above,
+        (Some(below), None) => levels[below],
+        (None, None) => unreachable!("levels mustn't be empty"),
+    }
+}
+
+fn diffuse(image: &GrayImage, levels: &[u8], kernel: &[(i32, i32, f32)]) -> GrayImage {
+    let width = image.width() as i32;
+    let height = image.height() as i32;
+    let mut values = image.iter().map(|v| *v as f32).collect::<Vec<f32>>();
+    let mut dithered = GrayImage::new(image.width(), image.height());
+    for y in 0..height {
+        for x in 0..width {
+            let value = values[(y * width + x) as usize];
+            let level = closest_level(levels, value);
+            dithered.put_pixel(x as u32, y as u32, Luma([level]));
+
+            let error = value - level as f32;
+            for (dx, dy, weight) in kernel {
+                let (nx, ny) = (x + dx, y + dy);
+                if (0..width).contains(&nx) && ny < height {
+                    values[(ny * width + nx) as usize] += error * weight;
+                }
+            }
+        }
+    }
+    dithered
+}
+
+fn ordered(image: &GrayImage, levels: &[u8]) -> GrayImage {
+    let mut dithered = GrayImage::new(image.width(), image.height());
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let value = pixel.0[0];
+        let index = levels.partition_point(|level| *level <= value);
+        let level = match (index.checked_sub(1), levels.get(index)) {
+            (Some(below), Some(above)) => {
+                let below = levels[below];
+                let fraction = (value - below) as f32 / (*above - below) as f32;
+                let threshold = (BAYER[y as usize % BAYER_SIZE][x as usize % BAYER_SIZE] as f32
+                    + 0.5)
+                    / (BAYER_SIZE * BAYER_SIZE) as f32;
+                if fraction > threshold {
+                    *above
+                } else {
+                    below
+                }
+            }
+            (Some(below), None) => levels[below],
+            (None, Some(above)) => *above,
+            (None, None) => unreachable!("levels mustn't be empty"),
+        };
+        dithered.put_pixel(x, y, Luma([level]));
+    }
+    dithered
+}
+
+#[cfg(test)]
+mod dither_tests {
+    use super::*;
+
+    #[test]
+    fn apply() {
+        let image = GrayImage::from_pixel(16, 16, Luma([64]));
+        for dither in [
+            Dither::FloydSteinberg,
+            Dither::Atkinson,
+            Dither::Sierra,
+            Dither::Bayer,
+        ] {
+            let dithered = dither.apply(&image, &[0, 255]);
+            assert!(dithered.iter().all(|v| *v == 0 || *v == 255));
+            let lit = dithered.iter().filter(|v| **v == 255).count();
+            assert!((40..=88).contains(&lit), "{:?} lit {} pixels", dither, lit);
+            assert_eq!(dither.apply(&image, &[]), image);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use image::{DynamicImage, RgbImage};
+
+use crate::{
+    char_grid::{Cell, CharGrid},
+    char_mapper::{map_image, CellSample, CharMapper},
+};
+
+pub const UPPER_HALF_BLOCK: char = '▀';
+
+pub trait AsHalfBlocks {
+    ///Draws two vertically stacked pixels per char, the top one as the foreground of `▀`
+    ///and the bottom one as its background.
+    fn as_half_blocks(&self) -> CharGrid;
+}
+
+///Draws the top pixel of a 1x2 block as the foreground of `▀` and the bottom one as its background.
+pub struct HalfBlockMapper;
+
+impl CharMapper for HalfBlockMapper {
+    fn cell_size(&self) -> (u32, u32) {
+        (1, 2)
+    }
+
+    fn map_cell(&self, sample: &CellSample) -> Cell {
+        let cell =
+            Cell::new(UPPER_HALF_BLOCK, sample.mean_luma()).with_foreground(sample.rgb(0, 0));
+        if sample.height > 1 {
+            cell.with_background(sample.rgb(0, 1))
+        } else {
+            cell
+        }
+    }
+}
+
+impl AsHalfBlocks for RgbImage {
+    fn as_half_blocks(&self) -> CharGrid {
+        map_image(&DynamicImage::ImageRgb8(self.clone()), &HalfBlockMapper)
+    }
+}
+
+impl AsHalfBlocks for DynamicImage {
+    fn as_half_blocks(&self) -> CharGrid {
+        self.to_rgb8().as_half_blocks()
+    }
+}
+
+#[cfg(test)]
+mod half_block_tests {
+    use image::Rgb;
+
+    use super::*;
+    use crate::color::ColorMode;
+
+    #[test]
+    fn as_half_blocks() {
+        let mut image = RgbImage::from_pixel(2, 3, Rgb([255, 0, 0]));
+        image.put_pixel(0, 1, Rgb([0, 0, 255]));
+        let chars = image.as_half_blocks().to_ansi(ColorMode::TrueColor);
+        let rows = chars.lines().collect::<Vec<&str>>();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].matches(UPPER_HALF_BLOCK).count(), 2);
+        assert!(rows[0].starts_with("\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m▀"));
+        assert_eq!(rows[1], "\x1b[38;2;255;0;0m▀▀\x1b[0m");
+    }
+}
@@ -1,23 +1,118 @@
-use std::{fs, io};
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Cursor, Read, Write},
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
+};
 
-use as_chars::{as_chars_image, AsChars};
-use brightness_char_map::BrightnessCharMap;
-use clap::{arg, value_parser, ArgMatches, Command};
-use image::{imageops::FilterType, io::Reader, DynamicImage, GrayImage};
+use as_chars::{as_chars_image, AsChars, GridLayout};
+use braille::{AsBraille, BrailleMapper, BRAILLE_CELL_HEIGHT, BRAILLE_CELL_WIDTH};
+use brightness_char_map::{cell_aspect, BrightnessCharMap};
+use char_grid::{expand_tabs, CharGrid};
+use char_map_file::CharMapFile;
+use char_mapper::Colored;
+use clap::{arg, error::ErrorKind, value_parser, Arg, ArgAction, ArgMatches, Command};
+use color::{parse_color, AsColoredChars, ColorMode};
+use dither::Dither;
+use half_block::AsHalfBlocks;
+use image::{imageops::FilterType, DynamicImage, ImageOutputFormat, Rgba, RgbaImage};
+use preprocess::{ImageOp, Pipeline};
 use rusttype::{Font, Scale};
+use shape_char_map::{ShapeCharMap, ShapeMetric};
+use theme::Theme;
+use transfer::TransferCurve;
+use tuning::{AutoTuner, QualityMetric, Tuning};
 
+pub mod animation;
 pub mod as_chars;
+pub mod batch;
+pub mod braille;
 pub mod brightness_char_map;
+pub mod char_grid;
+pub mod char_map_file;
+pub mod char_mapper;
+pub mod color;
+pub mod dither;
+pub mod half_block;
+pub mod preprocess;
+pub mod shape_char_map;
+pub mod sizing;
+pub mod svg;
+pub mod theme;
+pub mod transfer;
+pub mod tuning;
 
-fn get_matches() -> ArgMatches {
+///Reads the input from stdin or writes the output to stdout when given as a path.
+const STDIO_PATH: &str = "-";
+
+fn get_command() -> Command {
     Command::new("char_art")
+        .subcommand_precedence_over_arg(true)
         .args(&[
-            arg!(-p --path <Path> "Input image path")
-                .required(true)
+            arg!(-p --path <Path> "Input image paths, directories or patterns such as sprites/*.png")
+                .required(false)
+                .num_args(1..)
+                .value_parser(value_parser!(String)),
+            arg!(-o --output [Template] "Write the chars to files instead of printing them, {stem}, {name}, {ext} and {index} are filled in per input")
                 .value_parser(value_parser!(String)),
+            arg!(-j --jobs [usize] "How many inputs to convert at the same time")
+                .value_parser(value_parser!(usize)),
             arg!(-s --shrink [u32] "Resize divide amount").value_parser(value_parser!(u32)),
+            arg!(--width [Columns] "Resize the output to this many chars wide")
+                .value_parser(value_parser!(u32)),
+            arg!(--height [Rows] "Resize the output to this many chars tall")
+                .value_parser(value_parser!(u32)),
+            arg!(--fit "Resize the output to fit inside the terminal"),
+            arg!(--"cell-aspect" [f32] "How many times taller than wide a char is (measured from the calibration font by default)")
+                .value_parser(sizing::parse_cell_aspect),
             arg!(-d --darken [i32] "Darken amount (input negative values to brighten)")
-                .value_parser(value_parser!(i32)),
+                .value_parser(value_parser!(i32))
+                .allow_negative_numbers(true),
+            arg!(--gamma [f32] "Gamma correction, values above 1 brighten the mid tones")
+                .value_parser(value_parser!(f32)),
+            arg!(--op [Op] "Adjust the sized image before converting it, in the given order together with --darken and --gamma: contrast=f32, gamma=f32, darken=i32, blur=sigma, sharpen=sigma, equalize, clahe[=clip], invert, threshold[=u8] or posterize[=levels]")
+                .value_parser(value_parser!(ImageOp))
+                .action(ArgAction::Append),
+            arg!(--curve [Curve] "Shape how brightness maps onto the chars, in the given order: gamma=f32, sigmoid=gain[,midpoint], piecewise=path or auto-levels")
+                .value_parser(value_parser!(TransferCurve))
+                .action(ArgAction::Append),
+            arg!(--auto [Metric] "Try several darken amounts, gammas and charsets and keep the one closest to the image by psnr or ssim")
+                .value_parser(value_parser!(QualityMetric))
+                .num_args(0..=1)
+                .default_missing_value("ssim")
+                .conflicts_with_all(["darken", "gamma", "braille", "half-blocks", "shapes"]),
+            arg!(--report "Print the values --auto picked and their score")
+                .requires("auto"),
+            arg!(--theme [Theme] "Background of the terminal or image: dark, light or auto to ask the terminal")
+                .value_parser(value_parser!(Theme)),
+            arg!(--"calibration-font" [Path] "Font used to measure each char's brightness")
+                .value_parser(value_parser!(String)),
+            arg!(--"calibration-size" [f32] "Text scale used to measure each char's brightness")
+                .value_parser(value_parser!(f32)),
+            arg!(--charmap [Path] "Load a char map saved by the calibrate command instead of measuring the font")
+                .value_parser(value_parser!(String)),
+            arg!(--charset [Chars] "Chars to draw with, either as a string or a path to a file containing them")
+                .value_parser(value_parser!(String)),
+            arg!(--braille "Pack every 2x4 pixel block into a braille char"),
+            arg!(--threshold [u8] "Brightness a pixel needs to raise a braille dot")
+                .value_parser(value_parser!(u8)),
+            arg!(--dither [Method] "Spread rounding errors over neighbouring chars: floyd-steinberg, atkinson, sierra or bayer")
+                .value_parser(value_parser!(Dither))
+                .num_args(0..=1)
+                .default_missing_value("floyd-steinberg"),
+            arg!(--color [Mode] "Colour the printed chars: none, 16, 256 or truecolor")
+                .value_parser(value_parser!(ColorMode)),
+            arg!(--loops [u32] "How many times to play an animation, 0 plays it forever")
+                .value_parser(value_parser!(u32)),
+            arg!(--fps [f32] "Play an animation at this many frames per second instead of its own timing")
+                .value_parser(value_parser!(f32)),
+            arg!(--"half-blocks" "Draw two coloured pixels per char using half block chars"),
+            arg!(--shapes [Metric] "Match the shape of every glyph sized tile instead of single pixels: mse or ssim")
+                .value_parser(value_parser!(ShapeMetric))
+                .num_args(0..=1)
+                .default_missing_value("mse"),
         ])
         .subcommand(
             Command::new("to_image")
@@ -26,11 +121,84 @@ fn get_matches() -> ArgMatches {
                     arg!(-p --path <Path> "output image path")
                         .required(true)
                         .value_parser(value_parser!(String)),
-                    arg!(-f --font [Path] "Font path").value_parser(value_parser!(String)),
-                    arg!(-s --size [f32] "Text scale amount").value_parser(value_parser!(f32)),
+                    arg!(--colored "Draw every char in the colour of the part of the image it replaces"),
+                    arg!(--fill [i32] "Fill every char's cell with its colour darkened by this amount")
+                        .value_parser(value_parser!(i32))
+                        .num_args(0..=1)
+                        .default_missing_value("128")
+                        .requires("colored"),
+                ])
+                .args(get_image_args()),
+        )
+        .subcommand(
+            Command::new("to_html")
+                .about("Save the converted image as a standalone HTML page.")
+                .args(&[
+                    arg!(-p --path <Path> "output page path")
+                        .required(true)
+                        .value_parser(value_parser!(String)),
+                    arg!(--colored "Colour every char like the part of the image it replaces"),
+                    arg!(--"font-family" [Name] "Font the page draws the chars with")
+                        .value_parser(value_parser!(String)),
                 ]),
         )
-        .get_matches()
+        .subcommand(
+            Command::new("to_svg")
+                .about("Save the converted image as an SVG.")
+                .args(&[
+                    arg!(-p --path <Path> "output SVG path")
+                        .required(true)
+                        .value_parser(value_parser!(String)),
+                    arg!(--colored "Colour every char like the part of the image it replaces"),
+                    arg!(--"font-family" [Name] "Font the SVG draws the chars with")
+                        .value_parser(value_parser!(String)),
+                ])
+                .args(get_image_args()),
+        )
+        .subcommand(
+            Command::new("render")
+                .about("Draw char art from a text file (or stdin) as an image.")
+                .args(&[
+                    arg!(-i --input [Path] "input text path, reads stdin when missing or -")
+                        .value_parser(value_parser!(String)),
+                    arg!(-p --path <Path> "output image path, writes a PNG to stdout when -")
+                        .required(true)
+                        .value_parser(value_parser!(String)),
+                    arg!(--"tab-width" [usize] "How many columns a tab stop is apart")
+                        .value_parser(value_parser!(usize)),
+                ])
+                .args(get_image_args()),
+        )
+        .subcommand(
+            Command::new("calibrate")
+                .about("Measure the calibration font and save the resulting char map.")
+                .args(&[arg!(-p --path <Path> "output char map path")
+                    .required(true)
+                    .value_parser(value_parser!(String))]),
+        )
+}
+
+///Options shared by every subcommand that draws chars to an image.
+fn get_image_args() -> [Arg; 8] {
+    [
+        arg!(-f --font [Path] "Font path").value_parser(value_parser!(String)),
+        arg!(-s --size [f32] "Text scale amount").value_parser(value_parser!(f32)),
+        arg!(--fg [Color] "Text colour as #rrggbb").value_parser(parse_color),
+        arg!(--bg [Color] "Background colour as #rrggbb").value_parser(parse_color),
+        arg!(--transparent "Leave the background transparent").conflicts_with("bg"),
+        arg!(--"letter-spacing" [f32] "Pixels added between neighbouring chars")
+            .value_parser(value_parser!(f32))
+            .allow_negative_numbers(true),
+        arg!(--"line-spacing" [f32] "Pixels added between rows")
+            .value_parser(value_parser!(f32))
+            .allow_negative_numbers(true),
+        arg!(--padding [u32] "Pixels of background around the chars")
+            .value_parser(value_parser!(u32)),
+    ]
+}
+
+fn get_matches() -> ArgMatches {
+    get_command().get_matches()
 }
 
 fn get_path(matches: &ArgMatches) -> Result<String, image::ImageError> {
@@ -42,11 +210,6 @@ fn get_path(matches: &ArgMatches) -> Result<String, image::ImageError> {
     }
 }
 
-fn get_image(matches: &ArgMatches) -> Result<DynamicImage, image::ImageError> {
-    let path = get_path(matches)?;
-    Reader::open(path)?.decode()
-}
-
 fn shrink_image(image: DynamicImage, amount: Option<&u32>) -> DynamicImage {
     match amount {
         Some(amount) => image.resize(
@@ -58,15 +221,54 @@ fn shrink_image(image: DynamicImage, amount: Option<&u32>) -> DynamicImage {
     }
 }
 
-fn darken_image(image: DynamicImage, amount: Option<&i32>) -> DynamicImage {
-    match amount {
-        Some(amount) => image.brighten(-*amount),
-        None => image,
+fn size_image(image: DynamicImage, matches: &ArgMatches, cell: (f32, f32)) -> DynamicImage {
+    let terminal = if matches.get_flag("fit") {
+        sizing::terminal_size()
+    } else {
+        None
+    };
+    let columns = matches
+        .get_one::<u32>("width")
+        .copied()
+        .or(terminal.map(|(columns, _)| columns));
+    let rows = matches
+        .get_one::<u32>("height")
+        .copied()
+        .or(terminal.map(|(_, rows)| rows.saturating_sub(1)));
+    sizing::fit_image(image, columns, rows, cell)
+}
+
+///Collects `--op`, `--darken` and `--gamma` into a pipeline in the order they were given.
+fn get_pipeline(matches: &ArgMatches) -> Pipeline {
+    let mut ops = Vec::new();
+    if let (Some(indices), Some(values)) =
+        (matches.indices_of("op"), matches.get_many::<ImageOp>("op"))
+    {
+        ops.extend(indices.zip(values.copied()));
+    }
+    if let (Some(index), Some(amount)) =
+        (matches.index_of("darken"), matches.get_one::<i32>("darken"))
+    {
+        ops.push((index, ImageOp::Darken(*amount)));
+    }
+    if let (Some(index), Some(gamma)) = (matches.index_of("gamma"), matches.get_one::<f32>("gamma"))
+    {
+        ops.push((index, ImageOp::Gamma(*gamma)));
     }
+    ops.sort_by_key(|(index, _)| *index);
+    ops.into_iter().map(|(_, op)| op).collect()
 }
 
-fn get_font(matches: &ArgMatches) -> Result<Vec<u8>, image::ImageError> {
-    match matches.get_one::<String>("font") {
+///Parses the font images and SVGs are drawn with.
+fn get_font(matches: &ArgMatches) -> Result<Font<'static>, image::ImageError> {
+    Ok(
+        Font::try_from_vec(get_font_bytes(matches.get_one::<String>("font"))?)
+            .ok_or(io::Error::from(io::ErrorKind::InvalidData))?,
+    )
+}
+
+fn get_font_bytes(path: Option<&String>) -> Result<Vec<u8>, image::ImageError> {
+    match path {
         Some(path) => Ok(fs::read(path)?),
         None => {
             Ok(include_bytes!("/home/joknavi/.local/share/fonts/RobotoMono-Regular.ttf").to_vec())
@@ -83,33 +285,684 @@ fn get_scale(matches: &ArgMatches) -> Result<Scale, image::ImageError> {
     }
 }
 
-fn get_chars_image<'a>(
-    chars: &'a str,
-    sub_matches: &'a ArgMatches,
-) -> Result<GrayImage, image::ImageError> {
-    let font = Font::try_from_vec(get_font(sub_matches)?)
-        .ok_or(io::Error::from(io::ErrorKind::NotFound))?;
+fn get_charset(matches: &ArgMatches) -> Result<Option<Vec<char>>, image::ImageError> {
+    let charset = match matches.get_one::<String>("charset") {
+        Some(charset) if Path::new(charset).is_file() => fs::read_to_string(charset)?,
+        Some(charset) => charset.to_string(),
+        None => return Ok(None),
+    };
+    let chars = charset
+        .chars()
+        .filter(|char| *char != '\n' && *char != '\r')
+        .collect::<Vec<char>>();
+    if chars.is_empty() {
+        return Err(image::ImageError::IoError(io::Error::from(
+            io::ErrorKind::InvalidInput,
+        )));
+    }
+    Ok(Some(chars))
+}
+
+fn calibrate_char_map(
+    matches: &ArgMatches,
+) -> Result<(BrightnessCharMap, CharMapFile), image::ImageError> {
+    const DEFAULT_CALIBRATION_SCALE: f32 = 40.0;
+
+    let font_bytes = get_font_bytes(matches.get_one::<String>("calibration-font"))?;
+    let font =
+        Font::try_from_bytes(&font_bytes).ok_or(io::Error::from(io::ErrorKind::InvalidData))?;
+    let size = *matches
+        .get_one::<f32>("calibration-size")
+        .unwrap_or(&DEFAULT_CALIBRATION_SCALE);
+    let char_map = match get_charset(matches)? {
+        Some(chars) => BrightnessCharMap::from_chars(chars, &font, Scale::uniform(size)),
+        None => BrightnessCharMap::from_font(&font, Scale::uniform(size)),
+    };
+    let file = CharMapFile::new(&char_map, &font_bytes, size);
+    Ok((char_map, file))
+}
+
+fn get_theme(matches: &ArgMatches) -> Theme {
+    matches
+        .get_one::<Theme>("theme")
+        .copied()
+        .unwrap_or(Theme::Dark)
+        .resolve()
+}
+
+fn get_char_map(
+    matches: &ArgMatches,
+    theme: Theme,
+) -> Result<BrightnessCharMap, image::ImageError> {
+    let char_map = if let Some(path) = matches.get_one::<String>("charmap") {
+        let file = CharMapFile::load(path)?;
+        if let Some(font) = matches.get_one::<String>("calibration-font") {
+            if !file.matches_font(&fs::read(font)?) {
+                eprintln!(
+                    "warning: {} was calibrated against a different font than {}",
+                    path, font
+                );
+            }
+        }
+        file.to_char_map()?
+    } else if !matches.contains_id("calibration-font")
+        && !matches.contains_id("calibration-size")
+        && !matches.contains_id("charset")
+    {
+        BrightnessCharMap::default()
+    } else {
+        calibrate_char_map(matches)?.0
+    };
+    let char_map = match theme {
+        Theme::Light => char_map.inverted(),
+        _ => char_map,
+    };
+    Ok(match matches.get_one::<f32>("cell-aspect") {
+        Some(cell_aspect) => char_map.with_cell_aspect(*cell_aspect),
+        None => char_map,
+    })
+}
+
+fn get_cell_aspect(matches: &ArgMatches) -> Result<f32, image::ImageError> {
+    if let Some(cell_aspect) = matches.get_one::<f32>("cell-aspect") {
+        return Ok(*cell_aspect);
+    }
+    if let Some(path) = matches.get_one::<String>("charmap") {
+        return Ok(CharMapFile::load(path)?.cell_aspect);
+    }
+    let font = Font::try_from_vec(get_font_bytes(
+        matches.get_one::<String>("calibration-font"),
+    )?)
+    .ok_or(io::Error::from(io::ErrorKind::InvalidData))?;
+    Ok(cell_aspect(&font))
+}
+
+fn get_shape_map(
+    matches: &ArgMatches,
+    metric: ShapeMetric,
+    theme: Theme,
+) -> Result<ShapeCharMap, image::ImageError> {
+    const DEFAULT_SHAPE_SCALE: f32 = 12.0;
+
+    let font = Font::try_from_vec(get_font_bytes(
+        matches.get_one::<String>("calibration-font"),
+    )?)
+    .ok_or(io::Error::from(io::ErrorKind::InvalidData))?;
+    let scale = Scale::uniform(
+        *matches
+            .get_one::<f32>("calibration-size")
+            .unwrap_or(&DEFAULT_SHAPE_SCALE),
+    );
+    let shape_map = match get_charset(matches)? {
+        Some(chars) => ShapeCharMap::from_chars(chars, &font, scale, metric),
+        None => ShapeCharMap::from_font(&font, scale, metric),
+    };
+    Ok(match theme {
+        Theme::Light => shape_map.inverted(),
+        _ => shape_map,
+    })
+}
+
+fn get_text(matches: &ArgMatches) -> Result<CharGrid, image::ImageError> {
+    const DEFAULT_TAB_WIDTH: usize = 8;
+
+    let text = match matches.get_one::<String>("input") {
+        Some(path) if path != STDIO_PATH => fs::read_to_string(path)?,
+        _ => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        }
+    };
+    let tab_width = *matches
+        .get_one::<usize>("tab-width")
+        .unwrap_or(&DEFAULT_TAB_WIDTH);
+    let chars = CharGrid::from_text(&expand_tabs(&text, tab_width));
+    if chars.width() == 0 {
+        return Err(image::ImageError::IoError(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "nothing to render",
+        )));
+    }
+    Ok(chars)
+}
+
+fn get_auto_tuner(
+    matches: &ArgMatches,
+    metric: QualityMetric,
+    char_map: &BrightnessCharMap,
+    theme: Theme,
+) -> Result<AutoTuner<'static>, image::ImageError> {
+    const DEFAULT_CALIBRATION_SCALE: f32 = 40.0;
+    const RENDER_SCALE: f32 = 12.0;
+
+    let font = Font::try_from_vec(get_font_bytes(
+        matches.get_one::<String>("calibration-font"),
+    )?)
+    .ok_or(io::Error::from(io::ErrorKind::InvalidData))?;
+    let mut char_maps = vec![(None, char_map.clone())];
+    if !matches.contains_id("charset") && !matches.contains_id("charmap") {
+        let size = *matches
+            .get_one::<f32>("calibration-size")
+            .unwrap_or(&DEFAULT_CALIBRATION_SCALE);
+        let simple = BrightnessCharMap::from_chars(
+            tuning::SIMPLE_CHARSET.chars(),
+            &font,
+            Scale::uniform(size),
+        )
+        .with_cell_aspect(char_map.cell_aspect());
+        let simple = match theme {
+            Theme::Light => simple.inverted(),
+            _ => simple,
+        };
+        char_maps.push((Some(tuning::SIMPLE_CHARSET.to_string()), simple));
+    }
+    Ok(AutoTuner {
+        metric,
+        char_maps,
+        font,
+        scale: Scale::uniform(RENDER_SCALE),
+        theme,
+    })
+}
+
+fn get_image_colors(matches: &ArgMatches, theme: Theme) -> (Rgba<u8>, Rgba<u8>) {
+    let foreground = *matches
+        .get_one::<Rgba<u8>>("fg")
+        .unwrap_or(&theme.foreground());
+    let background = if matches.get_flag("transparent") {
+        //Keeping the text colour makes the anti-aliased glyph edges fade out instead of darkening.
+        Rgba([foreground[0], foreground[1], foreground[2], u8::MIN])
+    } else {
+        *matches
+            .get_one::<Rgba<u8>>("bg")
+            .unwrap_or(&theme.background())
+    };
+    (foreground, background)
+}
+
+fn get_layout(matches: &ArgMatches) -> GridLayout {
+    GridLayout {
+        letter_spacing: *matches.get_one::<f32>("letter-spacing").unwrap_or(&0.0),
+        line_spacing: *matches.get_one::<f32>("line-spacing").unwrap_or(&0.0),
+        padding: *matches.get_one::<u32>("padding").unwrap_or(&0),
+    }
+}
+
+fn get_chars_image(
+    chars: &CharGrid,
+    font: &Font,
+    sub_matches: &ArgMatches,
+    theme: Theme,
+) -> Result<RgbaImage, image::ImageError> {
+    let scale = get_scale(sub_matches)?;
+    let (foreground, background) = get_image_colors(sub_matches, theme);
+    let layout = get_layout(sub_matches);
+
+    Ok(as_chars_image(
+        chars, font, scale, layout, foreground, background,
+    ))
+}
+
+fn get_chars_svg(
+    chars: &CharGrid,
+    font: &Font,
+    sub_matches: &ArgMatches,
+    theme: Theme,
+) -> Result<String, image::ImageError> {
     let scale = get_scale(sub_matches)?;
+    let (foreground, background) = get_image_colors(sub_matches, theme);
+    let font_family = sub_matches
+        .get_one::<String>("font-family")
+        .map_or("monospace", String::as_str);
+
+    Ok(svg::as_chars_svg(
+        chars,
+        font,
+        scale,
+        get_layout(sub_matches),
+        foreground,
+        background,
+        font_family,
+    ))
+}
 
-    Ok(as_chars_image(chars, &font, scale))
+///Turns every decoded image, or every frame of an animation, into chars the same way.
+struct Converter<'a> {
+    matches: &'a ArgMatches,
+    braille: bool,
+    half_blocks: bool,
+    colored: bool,
+    ///How much braille and half block images are stretched to make up for the cell aspect.
+    stretch: f32,
+    color: ColorMode,
+    theme: Theme,
+    pipeline: Pipeline,
+    curves: Vec<TransferCurve>,
+    char_map: Option<BrightnessCharMap>,
+    shape_map: Option<ShapeCharMap>,
+    tuner: Option<AutoTuner<'static>>,
+    ///The font `to_image` and `to_svg` draw with, parsed once for every input and frame.
+    font: Option<Font<'static>>,
+}
+
+impl Converter<'_> {
+    fn convert(&self, image: DynamicImage, tuning: Option<&Tuning>) -> CharGrid {
+        let mut image = self.pipeline.apply(self.resize(image));
+        let char_map = match (tuning, &self.tuner) {
+            (Some(tuning), Some(tuner)) => {
+                image = preprocess::adjust_gamma(image.brighten(-tuning.darken), tuning.gamma);
+                Some(&tuner.char_maps[tuning.char_map].1)
+            }
+            _ => self.char_map.as_ref(),
+        };
+        self.to_chars(&image, char_map)
+    }
+
+    ///Picks the darken amount, gamma and char map for `image` when `--auto` is given.
+    fn tune(&self, image: DynamicImage) -> Option<Tuning> {
+        let tuner = self.tuner.as_ref()?;
+        let image = self.pipeline.apply(self.resize(image));
+        Some(tuner.tune(&image, |image, char_map| {
+            self.to_chars(image, Some(char_map))
+        }))
+    }
+
+    ///Shrinks and sizes the image so every pixel, or block of pixels, becomes a single char.
+    fn resize(&self, image: DynamicImage) -> DynamicImage {
+        let matches = self.matches;
+        let mut image = shrink_image(image, matches.get_one::<u32>("shrink"));
+        let cell = if self.braille || self.half_blocks {
+            image = sizing::stretch_height(image, self.stretch);
+            if self.braille {
+                (BRAILLE_CELL_WIDTH as f32, BRAILLE_CELL_HEIGHT as f32)
+            } else {
+                (1.0, 2.0)
+            }
+        } else if let Some(shape_map) = &self.shape_map {
+            (
+                shape_map.tile_width() as f32,
+                shape_map.tile_height() as f32,
+            )
+        } else {
+            (1.0, self.get_char_map().cell_aspect())
+        };
+        size_image(image, matches, cell)
+    }
+
+    fn to_chars(&self, image: &DynamicImage, char_map: Option<&BrightnessCharMap>) -> CharGrid {
+        let matches = self.matches;
+        if self.braille {
+            let threshold = *matches.get_one::<u8>("threshold").unwrap_or(&128);
+            let inverted = self.theme == Theme::Light;
+            match matches.get_one::<Dither>("dither") {
+                None if self.colored => image.as_mapped_chars(&Colored(BrailleMapper {
+                    threshold,
+                    inverted,
+                })),
+                dither => {
+                    let mut chars = image.as_braille(threshold, inverted, dither.copied());
+                    if self.colored {
+                        chars.color_from(image);
+                    }
+                    chars
+                }
+            }
+        } else if self.half_blocks {
+            image.as_half_blocks()
+        } else if let Some(shape_map) = &self.shape_map {
+            if self.colored {
+                image.as_mapped_chars(&Colored(shape_map))
+            } else {
+                image.as_shape_chars(shape_map)
+            }
+        } else {
+            let char_map = char_map.expect("the char map is built for every other mode");
+            let curved;
+            let char_map = if self.curves.is_empty() {
+                char_map
+            } else {
+                let curve = transfer::transfer_lut(
+                    &self.curves,
+                    &image.to_luma8(),
+                    char_map.brightness_range(),
+                );
+                curved = char_map.clone().with_curve(curve);
+                &curved
+            };
+            if let Some(dither) = matches.get_one::<Dither>("dither") {
+                let mut chars = image.as_dithered_chars(char_map, *dither);
+                if self.colored {
+                    chars.color_from(image);
+                }
+                chars
+            } else if self.colored {
+                image.as_colored_chars(char_map)
+            } else {
+                image.as_chars(char_map)
+            }
+        }
+    }
+
+    ///The path every converted input is written to, with `{stem}` style placeholders still in it.
+    ///`None` when the chars are printed to the terminal.
+    fn output_template(&self) -> Result<Option<String>, image::ImageError> {
+        let matches = self.matches;
+        let export = matches
+            .subcommand_matches("to_image")
+            .or(matches.subcommand_matches("to_html"))
+            .or(matches.subcommand_matches("to_svg"));
+        Ok(match export {
+            Some(sub_matches) => Some(get_path(sub_matches)?),
+            None => matches.get_one::<String>("output").cloned(),
+        })
+    }
+
+    ///Converts a single input and writes it to its output file.
+    ///Returns the frames to print instead when there's no output file.
+    fn convert_file(
+        &self,
+        input: &Path,
+        index: usize,
+        animate_terminal: bool,
+    ) -> Result<Vec<(String, Duration)>, image::ImageError> {
+        let matches = self.matches;
+        let to_image = matches.subcommand_matches("to_image");
+        let output = self
+            .output_template()?
+            .map(|template| batch::output_path(&template, input, index));
+        let animate = match &output {
+            //Animations written to stdout become GIFs, single images PNGs.
+            Some(path) => {
+                to_image.is_some()
+                    && (path == Path::new(STDIO_PATH)
+                        || path
+                            .extension()
+                            .is_some_and(|extension| extension.eq_ignore_ascii_case("gif")))
+            }
+            None => animate_terminal,
+        };
+
+        let frames = if input == Path::new(STDIO_PATH) {
+            let mut bytes = Vec::new();
+            io::stdin().lock().read_to_end(&mut bytes)?;
+            animation::decode_frame_bytes(&bytes, None, animate)?
+        } else {
+            animation::decode_frames(input, animate)?
+        };
+        //Animations are tuned once, on their first frame, so they don't flicker.
+        let tuning = frames
+            .first()
+            .and_then(|(image, _)| self.tune(image.clone()));
+        if let Some(tuning) = &tuning {
+            if matches.get_flag("report") {
+                eprintln!("{}: {}", input.display(), self.describe(tuning));
+            }
+        }
+        let fps = matches.get_one::<f32>("fps").copied();
+        let mut frames = frames
+            .into_iter()
+            .map(|(image, delay)| {
+                (
+                    self.convert(image, tuning.as_ref()),
+                    animation::frame_delay(delay, fps),
+                )
+            })
+            .collect::<Vec<(CharGrid, Duration)>>();
+
+        let Some(path) = output else {
+            return Ok(frames
+                .iter()
+                .map(|(chars, delay)| (chars.to_ansi(self.color), *delay))
+                .collect());
+        };
+        if let Some(sub_matches) = to_image {
+            let mut images = Vec::with_capacity(frames.len());
+            for (chars, delay) in &mut frames {
+                if let Some(amount) = sub_matches.get_one::<i32>("fill") {
+                    chars.fill_backgrounds(*amount);
+                }
+                images.push((
+                    get_chars_image(chars, self.get_font(), sub_matches, self.theme)?,
+                    *delay,
+                ));
+            }
+            let loops = *matches.get_one::<u32>("loops").unwrap_or(&0);
+            if images.len() == 1 {
+                save_image(&path, images.remove(0).0)?;
+            } else if path == Path::new(STDIO_PATH) {
+                let mut bytes = Vec::new();
+                animation::write_gif(&mut bytes, images, loops)?;
+                write_output(&path, &bytes)?;
+            } else {
+                animation::save_gif(&path, images, loops)?;
+            }
+        } else if let Some(sub_matches) = matches.subcommand_matches("to_html") {
+            let font_family = sub_matches
+                .get_one::<String>("font-family")
+                .map_or("monospace", String::as_str);
+            let page = frames[0].0.to_html_page(
+                font_family,
+                self.theme.foreground(),
+                self.theme.background(),
+            );
+            write_output(&path, page.as_bytes())?;
+        } else if let Some(sub_matches) = matches.subcommand_matches("to_svg") {
+            write_output(
+                &path,
+                get_chars_svg(&frames[0].0, self.get_font(), sub_matches, self.theme)?.as_bytes(),
+            )?;
+        } else {
+            write_output(&path, frames[0].0.to_ansi(self.color).as_bytes())?;
+        }
+        Ok(Vec::new())
+    }
+
+    ///The options that reproduce `tuning`, followed by its score.
+    fn describe(&self, tuning: &Tuning) -> String {
+        let mut description = format!("--darken {} --gamma {}", tuning.darken, tuning.gamma);
+        let tuner = self.tuner.as_ref().expect("only tuned with a tuner");
+        if let Some(charset) = &tuner.char_maps[tuning.char_map].0 {
+            description.push_str(&format!(" --charset \"{}\"", charset));
+        }
+        description.push_str(&format!(" ({} {:.4})", tuner.metric.name(), tuning.score));
+        description
+    }
+
+    fn get_font(&self) -> &Font<'static> {
+        self.font
+            .as_ref()
+            .expect("the font is parsed for to_image and to_svg")
+    }
+
+    fn get_char_map(&self) -> &BrightnessCharMap {
+        self.char_map
+            .as_ref()
+            .expect("the char map is built for every other mode")
+    }
+}
+
+///Writes `contents` to `path`, or to stdout if `path` is `-`.
+fn write_output(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if path == Path::new(STDIO_PATH) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(contents)?;
+        stdout.flush()
+    } else {
+        fs::write(path, contents)
+    }
+}
+
+///Saves `image` to `path`, or writes it to stdout as a PNG if `path` is `-`.
+fn save_image(path: &Path, image: RgbaImage) -> Result<(), image::ImageError> {
+    if path == Path::new(STDIO_PATH) {
+        let mut bytes = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(image).write_to(&mut bytes, ImageOutputFormat::Png)?;
+        write_output(path, bytes.get_ref())?;
+    } else {
+        image.save(path)?;
+    }
+    Ok(())
+}
+
+///Prints converted chars, playing them as an animation if there's more than one frame.
+fn print_frames(frames: &[(String, Duration)], loops: u32) -> Result<(), image::ImageError> {
+    match frames {
+        [] => {}
+        [(chars, _)] => println!("{}", chars),
+        frames => animation::play(frames, loops, &mut io::stdout().lock())?,
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), image::ImageError> {
     let matches = get_matches();
-    let mut image = get_image(&matches)?;
 
-    image = shrink_image(image, matches.get_one::<u32>("shrink"));
-    image = darken_image(image, matches.get_one::<i32>("darken"));
-
-    let char_map = BrightnessCharMap::default();
-    let chars = image.as_chars(&char_map);
+    if let Some(sub_matches) = matches.subcommand_matches("calibrate") {
+        let (_, file) = calibrate_char_map(&matches)?;
+        file.save(get_path(sub_matches)?)?;
+        return Ok(());
+    }
+    if let Some(sub_matches) = matches.subcommand_matches("render") {
+        let chars = get_text(sub_matches)?;
+        let font = get_font(sub_matches)?;
+        save_image(
+            Path::new(&get_path(sub_matches)?),
+            get_chars_image(&chars, &font, sub_matches, get_theme(&matches))?,
+        )?;
+        return Ok(());
+    }
+    if !matches.contains_id("path") {
+        get_command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "the following required arguments were not provided:\n  --path <Path>",
+            )
+            .exit();
+    }
 
-    if let Some(sub_matches) = matches.subcommand_matches("to_image") {
-        let char_image = get_chars_image(&chars, sub_matches)?;
-        let path = get_path(sub_matches)?;
-        char_image.save(&path)?;
+    let theme = get_theme(&matches);
+    let to_image = matches.subcommand_matches("to_image");
+    let to_html = matches.subcommand_matches("to_html");
+    let to_svg = matches.subcommand_matches("to_svg");
+    let braille = matches.get_flag("braille");
+    let half_blocks = matches.get_flag("half-blocks") && to_image.is_none();
+    let shape_map = match matches.get_one::<ShapeMetric>("shapes") {
+        Some(metric) => Some(get_shape_map(&matches, *metric, theme)?),
+        None => None,
+    };
+    let char_map = if braille || half_blocks || shape_map.is_some() {
+        None
+    } else {
+        Some(get_char_map(&matches, theme)?)
+    };
+    let stretch = if braille || half_blocks {
+        2.0 / get_cell_aspect(&matches)?
     } else {
-        println!("{}", chars);
+        1.0
+    };
+
+    let color = match (to_image, matches.get_one::<ColorMode>("color")) {
+        (Some(_), _) => ColorMode::None,
+        (None, None) if half_blocks => ColorMode::TrueColor,
+        (None, color) => *color.unwrap_or(&ColorMode::None),
+    };
+    let colored = match to_image.or(to_html).or(to_svg) {
+        Some(sub_matches) => sub_matches.get_flag("colored"),
+        None => color != ColorMode::None,
+    };
+    let tuner = match (matches.get_one::<QualityMetric>("auto"), &char_map) {
+        (Some(metric), Some(char_map)) => Some(get_auto_tuner(&matches, *metric, char_map, theme)?),
+        _ => None,
+    };
+    let converter = Converter {
+        matches: &matches,
+        braille,
+        half_blocks,
+        colored,
+        stretch,
+        color,
+        theme,
+        pipeline: get_pipeline(&matches),
+        curves: matches
+            .get_many::<TransferCurve>("curve")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        char_map,
+        shape_map,
+        tuner,
+        font: match to_image.or(to_svg) {
+            Some(sub_matches) => Some(get_font(sub_matches)?),
+            None => None,
+        },
+    };
+
+    let paths = matches
+        .get_many::<String>("path")
+        .expect("checked above")
+        .cloned()
+        .collect::<Vec<String>>();
+    if paths.iter().filter(|path| *path == STDIO_PATH).count() > 1 {
+        get_command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "`-` can only be given once, stdin can't be read twice",
+            )
+            .exit();
+    }
+    let inputs = batch::expand_inputs(&paths)?;
+    if inputs.is_empty() {
+        return Err(image::ImageError::IoError(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no images match the given paths",
+        )));
+    }
+    if let Some(template) = converter.output_template()? {
+        let outputs = inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| batch::output_path(&template, input, index))
+            .collect::<HashSet<PathBuf>>();
+        if outputs.len() < inputs.len() {
+            get_command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    "several inputs would be written to the same file, add {stem} or {index} to the output path",
+                )
+                .exit();
+        }
+    }
+    let workers = match matches.get_one::<usize>("jobs") {
+        Some(jobs) => *jobs,
+        None => thread::available_parallelism().map_or(1, usize::from),
+    };
+    let loops = *matches.get_one::<u32>("loops").unwrap_or(&0);
+    let animate = inputs.len() == 1;
+    let mut results = batch::run_parallel(&inputs, workers, |index, input| {
+        converter.convert_file(input, index, animate)
+    });
+
+    if inputs.len() == 1 {
+        return print_frames(&results.remove(0)?, loops);
+    }
+    let mut failures = Vec::new();
+    for (input, result) in inputs.iter().zip(results) {
+        match result {
+            Ok(frames) => print_frames(&frames, loops)?,
+            Err(error) => failures.push((input, error)),
+        }
+    }
+    eprintln!(
+        "Converted {} of {} files",
+        inputs.len() - failures.len(),
+        inputs.len()
+    );
+    for (input, error) in &failures {
+        eprintln!("  {}: {}", input.display(), error);
+    }
+    if !failures.is_empty() {
+        process::exit(1);
     }
 
     Ok(())
@@ -0,0 +1,331 @@
+use std::str::FromStr;
+
+use image::{DynamicImage, GrayImage, Pixel, RgbaImage};
+
+const DEFAULT_CLAHE_CLIP_LIMIT: f32 = 2.0;
+const CLAHE_TILES: u32 = 8;
+const DEFAULT_THRESHOLD: u8 = 128;
+const DEFAULT_POSTERIZE_LEVELS: u8 = 4;
+const LUT_LENGTH: usize = u8::MAX as usize + 1;
+
+///A single adjustment made to an image before it's turned into chars.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageOp {
+    ///Scales every channel away from mid grey by this factor, so 1 leaves the image as is.
+    Contrast(f32),
+    ///Raises every channel to the power of `1 / gamma`, values above 1 brighten the mid tones.
+    Gamma(f32),
+    ///Subtracts this amount from every channel, negative amounts brighten the image.
+    Darken(i32),
+    ///Gaussian blur with this sigma.
+    Blur(f32),
+    ///Unsharp mask with this sigma.
+    Sharpen(f32),
+    ///Spreads the brightnesses evenly over the whole range.
+    Equalize,
+    ///Equalizes every tile of an 8x8 grid separately, limiting how much contrast it adds.
+    Clahe(f32),
+    ///Flips every channel, so black becomes white and white becomes black.
+    Invert,
+    ///Turns every pixel brighter than this white and every other pixel black.
+    Threshold(u8),
+    ///Rounds every channel to this many evenly spaced levels.
+    Posterize(u8),
+}
+
+impl ImageOp {
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        match *self {
+            Self::Contrast(factor) => map_channels(image, |value| {
+                let mid = u8::MAX as f32 / 2.0;
+                ((value as f32 - mid) * factor + mid).round() as u8
+            }),
+            Self::Gamma(gamma) => adjust_gamma(image, gamma),
+            Self::Darken(amount) => image.brighten(-amount),
+            Self::Blur(sigma) => image.blur(sigma),
+            Self::Sharpen(sigma) => image.unsharpen(sigma, 0),
+            Self::Equalize => {
+                let luma = image.to_luma8();
+                let lut = equalize_lut(luma.iter().copied());
+                map_luma(image, |x, y| lut[luma.get_pixel(x, y)[0] as usize])
+            }
+            Self::Clahe(clip_limit) => {
+                let luma = image.to_luma8();
+                let equalized = clahe(&luma, clip_limit);
+                map_luma(image, |x, y| equalized.get_pixel(x, y)[0])
+            }
+            Self::Invert => {
+                let mut image = image;
+                image.invert();
+                image
+            }
+            Self::Threshold(level) => {
+                let luma = image.to_luma8();
+                let mut image = image.into_rgba8();
+                for (x, y, pixel) in image.enumerate_pixels_mut() {
+                    let value = if luma.get_pixel(x, y)[0] > level {
+                        u8::MAX
+                    } else {
+                        u8::MIN
+                    };
+                    pixel.0[..3].fill(value);
+                }
+                DynamicImage::ImageRgba8(image)
+            }
+            Self::Posterize(levels) => {
+                let steps = levels.max(2) as f32 - 1.0;
+                map_channels(image, |value| {
+                    let level = (value as f32 / u8::MAX as f32 * steps).round();
+                    (level / steps * u8::MAX as f32).round() as u8
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for ImageOp {
+    type Err = String;
+
+    ///Parses `name=value`, or just `name` for operations without a value or with a default one.
+    fn from_str(op: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match op.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (op.trim(), None),
+        };
+        let number = |default: Option<f32>| -> Result<f32, String> {
+            match (value, default) {
+                (Some(value), _) => value
+                    .parse::<f32>()
+                    .map_err(|_| format!("`{}` needs a number, got `{}`", name, value)),
+                (None, Some(default)) => Ok(default),
+                (None, None) => Err(format!("`{}` needs a value, as in {}=1.5", name, name)),
+            }
+        };
+        let level = |default: u8| -> Result<u8, String> {
+            match value {
+                Some(value) => value.parse::<u8>().map_err(|_| {
+                    format!("`{}` needs a number from 0 to 255, got `{}`", name, value)
+                }),
+                None => Ok(default),
+            }
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "contrast" => Ok(Self::Contrast(number(None)?)),
+            "gamma" => Ok(Self::Gamma(number(None)?)),
+            "darken" => match value.map(str::parse::<i32>) {
+                Some(Ok(amount)) => Ok(Self::Darken(amount)),
+                Some(Err(_)) => Err(format!("`darken` needs a whole number, got `{}`", op)),
+                None => Err("`darken` needs a value, as in darken=40".to_string()),
+            },
+            "blur" => Ok(Self::Blur(number(None)?)),
+            "sharpen" | "unsharp" => Ok(Self::Sharpen(number(None)?)),
+            "equalize" => Ok(Self::Equalize),
+            "clahe" => Ok(Self::Clahe(number(Some(DEFAULT_CLAHE_CLIP_LIMIT))?)),
+            "invert" => Ok(Self::Invert),
+            "threshold" => Ok(Self::Threshold(level(DEFAULT_THRESHOLD)?)),
+            "posterize" => Ok(Self::Posterize(level(DEFAULT_POSTERIZE_LEVELS)?)),
+            _ => Err(format!(
+                "unknown operation `{}`, expected contrast, gamma, darken, blur, sharpen, equalize, clahe, invert, threshold or posterize",
+                name
+            )),
+        }
+    }
+}
+
+///Operations applied one after the other, in the order they were added.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pipeline {
+    ops: Vec<ImageOp>,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Self::default()
+    }
+
+    ///Adds `op` to the end of the pipeline.
+    pub fn with_op(mut self, op: ImageOp) -> Pipeline {
+        self.ops.push(op);
+        self
+    }
+
+    pub fn ops(&self) -> &[ImageOp] {
+        &self.ops
+    }
+
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        self.ops.iter().fold(image, |image, op| op.apply(image))
+    }
+}
+
+impl FromIterator<ImageOp> for Pipeline {
+    fn from_iter<T: IntoIterator<Item = ImageOp>>(ops: T) -> Self {
+        Self {
+            ops: ops.into_iter().collect(),
+        }
+    }
+}
+
+///Runs every colour channel through `map`, leaving the alpha channel alone.
+pub fn map_channels(image: DynamicImage, map: impl Fn(u8) -> u8) -> DynamicImage {
+    let lut: [u8; LUT_LENGTH] = std::array::from_fn(|value| map(value as u8));
+    let mut image = image.into_rgba8();
+    for pixel in image.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = lut[*channel as usize];
+        }
+    }
+    DynamicImage::ImageRgba8(image)
+}
+
+///Raises every channel to the power of `1 / gamma`, so values above 1 brighten the mid tones.
+pub fn adjust_gamma(image: DynamicImage, gamma: f32) -> DynamicImage {
+    if (gamma - 1.0).abs() < f32::EPSILON {
+        return image;
+    }
+    map_channels(image, |value| {
+        ((value as f32 / u8::MAX as f32).powf(1.0 / gamma) * u8::MAX as f32).round() as u8
+    })
+}
+
+///Moves every pixel to the brightness `luma` picks for it, shifting its channels equally to
+///keep its colour.
+fn map_luma(image: DynamicImage, luma: impl Fn(u32, u32) -> u8) -> DynamicImage {
+    let mut image: RgbaImage = image.into_rgba8();
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let shift = luma(x, y) as i32 - pixel.to_luma()[0] as i32;
+        for channel in &mut pixel.0[..3] {
+            *channel = (*channel as i32 + shift).clamp(u8::MIN as i32, u8::MAX as i32) as u8;
+        }
+    }
+    DynamicImage::ImageRgba8(image)
+}
+
+fn histogram(values: impl Iterator<Item = u8>) -> [u32; LUT_LENGTH] {
+    let mut histogram = [0u32; LUT_LENGTH];
+    for value in values {
+        histogram[value as usize] += 1;
+    }
+    histogram
+}
+
+fn histogram_lut(histogram: &[u32; LUT_LENGTH]) -> [u8; LUT_LENGTH] {
+    let total = histogram.iter().sum::<u32>().max(1) as f32;
+    let mut cumulative = 0;
+    std::array::from_fn(|value| {
+        cumulative += histogram[value];
+        (cumulative as f32 / total * u8::MAX as f32).round() as u8
+    })
+}
+
+fn equalize_lut(values: impl Iterator<Item = u8>) -> [u8; LUT_LENGTH] {
+    histogram_lut(&histogram(values))
+}
+
+///Contrast limited adaptive histogram equalization.
+///Every tile's histogram is clipped at `clip_limit` times its average bin and the clipped
+///counts are spread over all bins. Pixels blend the lookups of the four closest tiles.
+pub fn clahe(image: &GrayImage, clip_limit: f32) -> GrayImage {
+    let (width, height) = image.dimensions();
+    let tiles_x = CLAHE_TILES.min(width).max(1);
+    let tiles_y = CLAHE_TILES.min(height).max(1);
+    let tile_width = width.div_ceil(tiles_x).max(1);
+    let tile_height = height.div_ceil(tiles_y).max(1);
+
+    let mut luts = Vec::with_capacity((tiles_x * tiles_y) as usize);
+    for tile_y in 0..tiles_y {
+        for tile_x in 0..tiles_x {
+            let xs = tile_x * tile_width..((tile_x + 1) * tile_width).min(width);
+            let ys = tile_y * tile_height..((tile_y + 1) * tile_height).min(height);
+            let mut histogram = histogram(
+                ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+                    .map(|(x, y)| image.get_pixel(x, y)[0]),
+            );
+            let total = histogram.iter().sum::<u32>();
+            let limit = ((clip_limit * total as f32 / LUT_LENGTH as f32) as u32).max(1);
+            let mut excess = 0;
+            for count in &mut histogram {
+                excess += count.saturating_sub(limit);
+                *count = (*count).min(limit);
+            }
+            for count in &mut histogram {
+                *count += excess / LUT_LENGTH as u32;
+            }
+            luts.push(histogram_lut(&histogram));
+        }
+    }
+
+    //Which two tile centres a coordinate falls between and how close it is to the second.
+    let neighbours = |position: u32, size: u32, tiles: u32| {
+        let centre = (position as f32 + 0.5) / size as f32 - 0.5;
+        let first = (centre.floor().max(0.0) as u32).min(tiles - 1);
+        let second = (first + 1).min(tiles - 1);
+        (first, second, (centre - first as f32).clamp(0.0, 1.0))
+    };
+    GrayImage::from_fn(width, height, |x, y| {
+        let value = image.get_pixel(x, y)[0] as usize;
+        let (left, right, dx) = neighbours(x, tile_width, tiles_x);
+        let (top, bottom, dy) = neighbours(y, tile_height, tiles_y);
+        let lookup =
+            |tile_x: u32, tile_y: u32| luts[(tile_y * tiles_x + tile_x) as usize][value] as f32;
+        let upper = lookup(left, top) * (1.0 - dx) + lookup(right, top) * dx;
+        let lower = lookup(left, bottom) * (1.0 - dx) + lookup(right, bottom) * dx;
+        image::Luma([(upper * (1.0 - dy) + lower * dy).round() as u8])
+    })
+}
+
+#[cfg(test)]
+mod preprocess_tests {
+    use image::Luma;
+
+    use super::*;
+
+    #[test]
+    fn parse_ops() {
+        assert_eq!("gamma=0.8".parse(), Ok(ImageOp::Gamma(0.8)));
+        assert_eq!("sharpen = 1.2".parse(), Ok(ImageOp::Sharpen(1.2)));
+        assert_eq!(
+            "clahe".parse(),
+            Ok(ImageOp::Clahe(DEFAULT_CLAHE_CLIP_LIMIT))
+        );
+        assert_eq!("posterize=3".parse(), Ok(ImageOp::Posterize(3)));
+        assert_eq!("darken=-20".parse(), Ok(ImageOp::Darken(-20)));
+        assert!("blur".parse::<ImageOp>().is_err());
+        assert!("threshold=300".parse::<ImageOp>().is_err());
+        assert!("emboss".parse::<ImageOp>().is_err());
+    }
+
+    #[test]
+    fn pipeline() {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_fn(4, 1, |x, _| Luma([x as u8 * 60])));
+        let pipeline = Pipeline::new()
+            .with_op(ImageOp::Invert)
+            .with_op(ImageOp::Threshold(128));
+        let result = pipeline.apply(image.clone()).into_luma8();
+        assert_eq!(result.into_raw(), vec![255, 255, 255, 0]);
+
+        let posterized = ImageOp::Posterize(2).apply(image).into_luma8();
+        assert_eq!(posterized.into_raw(), vec![0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn adjust_gamma() {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_pixel(1, 1, Luma([64])));
+        let brightened = super::adjust_gamma(image.clone(), 2.0).into_luma8();
+        assert_eq!(brightened.get_pixel(0, 0)[0], 128);
+        assert_eq!(super::adjust_gamma(image.clone(), 1.0), image);
+    }
+
+    #[test]
+    fn equalize() {
+        let image = GrayImage::from_fn(16, 16, |x, _| Luma([100 + x as u8]));
+        let equalized = ImageOp::Equalize
+            .apply(DynamicImage::ImageLuma8(image.clone()))
+            .into_luma8();
+        assert_eq!(equalized.get_pixel(15, 0)[0], 255);
+        assert!(equalized.get_pixel(0, 0)[0] < 20);
+
+        let clahe = clahe(&image, DEFAULT_CLAHE_CLIP_LIMIT);
+        assert!(clahe.get_pixel(0, 0)[0] < clahe.get_pixel(15, 0)[0]);
+    }
+}
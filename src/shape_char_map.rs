@@ -0,0 +1,252 @@
+use std::str::FromStr;
+
+use image::GrayImage;
+use rusttype::{point, Font, Scale};
+
+use crate::{
+    brightness_char_map::{CHARS, FONT},
+    char_grid::Cell,
+    char_mapper::{CellSample, CharMapper},
+};
+
+const SCALE: f32 = 12.0;
+const COLOR: f32 = u8::MAX as f32;
+const SSIM_C1: f32 = 0.01 * 0.01;
+const SSIM_C2: f32 = 0.03 * 0.03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShapeMetric {
+    ///Picks the glyph with the lowest mean squared error.
+    Mse,
+    ///Picks the glyph with the highest structural similarity.
+    Ssim,
+}
+
+impl FromStr for ShapeMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mse" => Ok(ShapeMetric::Mse),
+            "ssim" => Ok(ShapeMetric::Ssim),
+            _ => Err(format!("unknown metric `{}`, expected mse or ssim", s)),
+        }
+    }
+}
+
+///Matches whole character cells against rasterised glyphs, so edges turn into chars like `/` or `_`.
+pub struct ShapeCharMap {
+    tile_width: u32,
+    tile_height: u32,
+    metric: ShapeMetric,
+    glyphs: Vec<(char, Vec<f32>)>,
+    ///Whether dark pixels are matched against the glyphs' ink instead of light ones.
+    inverted: bool,
+}
+
+impl ShapeCharMap {
+    fn new() -> ShapeCharMap {
+        let font = Font::try_from_bytes(FONT).unwrap();
+        Self::from_font(&font, Scale::uniform(SCALE), ShapeMetric::Mse)
+    }
+
+    ///Rasterises every char of `font` at `scale`. Each char cell of the input image is as large as one glyph.
+    pub fn from_font(font: &Font, scale: Scale, metric: ShapeMetric) -> ShapeCharMap {
+        Self::from_chars(CHARS, font, scale, metric)
+    }
+
+    ///Like [`ShapeCharMap::from_font`], but only matches against `chars`.
+    pub fn from_chars(
+        chars: impl IntoIterator<Item = char>,
+        font: &Font,
+        scale: Scale,
+        metric: ShapeMetric,
+    ) -> ShapeCharMap {
+        let v_metrics = font.v_metrics(scale);
+        let tile_width = font
+            .glyph(' ')
+            .scaled(scale)
+            .h_metrics()
+            .advance_width
+            .ceil()
+            .max(1.0) as u32;
+        let tile_height = (v_metrics.ascent - v_metrics.descent).ceil().max(1.0) as u32;
+
+        let mut glyphs: Vec<(char, Vec<f32>)> = Vec::new();
+        for char in chars {
+            if glyphs.iter().any(|(seen, _)| *seen == char) {
+                continue;
+            }
+            let mut coverage = vec![0.0; (tile_width * tile_height) as usize];
+            let glyph = font
+                .glyph(char)
+                .scaled(scale)
+                .positioned(point(0.0, v_metrics.ascent));
+            if let Some(bounds) = glyph.pixel_bounding_box() {
+                glyph.draw(|x, y, v| {
+                    let x = bounds.min.x + x as i32;
+                    let y = bounds.min.y + y as i32;
+                    if (0..tile_width as i32).contains(&x) && (0..tile_height as i32).contains(&y) {
+                        coverage[(y as u32 * tile_width + x as u32) as usize] = v;
+                    }
+                });
+            }
+            glyphs.push((char, coverage));
+        }
+
+        Self {
+            tile_width,
+            tile_height,
+            metric,
+            glyphs,
+            inverted: false,
+        }
+    }
+
+    ///Matches the dark parts of the image against the glyphs, for dark chars on a light background.
+    pub fn inverted(mut self) -> ShapeCharMap {
+        self.inverted = !self.inverted;
+        self
+    }
+
+    pub fn tile_width(&self) -> u32 {
+        self.tile_width
+    }
+
+    pub fn tile_height(&self) -> u32 {
+        self.tile_height
+    }
+
+    ///Returns the char whose glyph looks most like `tile`.
+    ///`tile` holds `tile_width * tile_height` brightnesses between 0.0 and 1.0, row by row.
+    pub fn closest(&self, tile: &[f32]) -> char {
+        let scores = self
+            .glyphs
+            .iter()
+            .map(|(char, coverage)| (*char, self.score(tile, coverage)));
+        match self.metric {
+            ShapeMetric::Mse => scores.min_by(|a, b| a.1.total_cmp(&b.1)),
+            ShapeMetric::Ssim => scores.max_by(|a, b| a.1.total_cmp(&b.1)),
+        }
+        .map_or(' ', |(char, _)| char)
+    }
+
+    ///Cuts `image` into glyph sized tiles and returns the tile at `(column, row)`.
+    pub fn tile(&self, image: &GrayImage, column: u32, row: u32) -> Vec<f32> {
+        let mut tile = Vec::with_capacity((self.tile_width * self.tile_height) as usize);
+        for y in 0..self.tile_height {
+            for x in 0..self.tile_width {
+                let brightness =
+                    image.get_pixel(column * self.tile_width + x, row * self.tile_height + y);
+                tile.push(self.coverage(brightness.0[0]));
+            }
+        }
+        tile
+    }
+
+    ///How much ink a pixel of `brightness` should be covered with, from 0.0 to 1.0.
+    fn coverage(&self, brightness: u8) -> f32 {
+        let coverage = brightness as f32 / COLOR;
+        if self.inverted {
+            1.0 - coverage
+        } else {
+            coverage
+        }
+    }
+
+    fn score(&self, tile: &[f32], coverage: &[f32]) -> f32 {
+        match self.metric {
+            ShapeMetric::Mse => {
+                tile.iter()
+                    .zip(coverage)
+                    .map(|(a, b)| (a - b).powi(2))
+                    .sum::<f32>()
+                    / tile.len() as f32
+            }
+            ShapeMetric::Ssim => ssim(tile, coverage),
+        }
+    }
+}
+
+impl CharMapper for ShapeCharMap {
+    fn cell_size(&self) -> (u32, u32) {
+        (self.tile_width, self.tile_height)
+    }
+
+    fn partial_cells(&self) -> bool {
+        false
+    }
+
+    fn map_cell(&self, sample: &CellSample) -> Cell {
+        let mut tile = Vec::with_capacity((self.tile_width * self.tile_height) as usize);
+        for y in 0..self.tile_height {
+            for x in 0..self.tile_width {
+                let brightness = sample.luma(x.min(sample.width - 1), y.min(sample.height - 1));
+                tile.push(self.coverage(brightness));
+            }
+        }
+        Cell::new(self.closest(&tile), sample.mean_luma())
+    }
+}
+
+impl Default for ShapeCharMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///Structural similarity of two equally sized tiles, computed over the whole tile as a single window.
+pub fn ssim(a: &[f32], b: &[f32]) -> f32 {
+    let length = a.len() as f32;
+    let mean_a = a.iter().sum::<f32>() / length;
+    let mean_b = b.iter().sum::<f32>() / length;
+    let (mut variance_a, mut variance_b, mut covariance) = (0.0, 0.0, 0.0);
+    for (a, b) in a.iter().zip(b) {
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+        covariance += (a - mean_a) * (b - mean_b);
+    }
+    variance_a /= length;
+    variance_b /= length;
+    covariance /= length;
+
+    ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+        / ((mean_a.powi(2) + mean_b.powi(2) + SSIM_C1) * (variance_a + variance_b + SSIM_C2))
+}
+
+#[cfg(test)]
+mod shape_char_map_tests {
+    use image::Luma;
+
+    use super::*;
+
+    #[test]
+    fn closest() {
+        let shape_map = ShapeCharMap::default();
+        let tile_size = (shape_map.tile_width() * shape_map.tile_height()) as usize;
+        assert_eq!(shape_map.closest(&vec![0.0; tile_size]), ' ');
+
+        let mut image = GrayImage::new(shape_map.tile_width(), shape_map.tile_height());
+        let x = shape_map.tile_width() / 2;
+        for y in 0..shape_map.tile_height() {
+            image.put_pixel(x - 1, y, Luma([255]));
+            image.put_pixel(x, y, Luma([255]));
+        }
+        assert_eq!(shape_map.closest(&shape_map.tile(&image, 0, 0)), '|');
+
+        let inverted = shape_map.inverted();
+        image::imageops::invert(&mut image);
+        assert_eq!(inverted.closest(&inverted.tile(&image, 0, 0)), '|');
+    }
+
+    #[test]
+    fn drops_partial_tiles() {
+        let shape_map = ShapeCharMap::default();
+        let image = GrayImage::new(
+            shape_map.tile_width() * 5 / 2,
+            shape_map.tile_height() * 3 / 2,
+        );
+        let grid = crate::as_chars::AsChars::as_shape_chars(&image, &shape_map);
+        assert_eq!((grid.width(), grid.height()), (2, 1));
+    }
+}
@@ -0,0 +1,84 @@
+use std::env;
+
+use image::{imageops::FilterType, DynamicImage};
+
+///Returns the size of the terminal in columns and rows.
+///Falls back to the `COLUMNS` and `LINES` environment variables when stdout isn't a terminal.
+pub fn terminal_size() -> Option<(u32, u32)> {
+    if let Some((width, height)) = terminal_size::terminal_size() {
+        return Some((width.0 as u32, height.0 as u32));
+    }
+    let columns = env::var("COLUMNS").ok()?.parse().ok()?;
+    let rows = env::var("LINES").ok()?.parse().ok()?;
+    Some((columns, rows))
+}
+
+///Resizes `image` so it turns into at most `columns` by `rows` chars while keeping its aspect ratio.
+///`cell` is the amount of pixels, horizontally and vertically, that end up in a single char.
+pub fn fit_image(
+    image: DynamicImage,
+    columns: Option<u32>,
+    rows: Option<u32>,
+    cell: (f32, f32),
+) -> DynamicImage {
+    if columns.is_none() && rows.is_none() {
+        return image;
+    }
+    let width = columns.map_or(u32::MAX, |columns| {
+        (columns.max(1) as f32 * cell.0).round() as u32
+    });
+    let height = rows.map_or(u32::MAX, |rows| {
+        (rows.max(1) as f32 * cell.1).round() as u32
+    });
+    image.resize(width, height, FilterType::Lanczos3)
+}
+
+///Stretches `image` vertically by `factor`, for renderers that expect chars to be twice as tall as wide.
+pub fn stretch_height(image: DynamicImage, factor: f32) -> DynamicImage {
+    if (factor - 1.0).abs() < f32::EPSILON {
+        return image;
+    }
+    let height = ((image.height() as f32 * factor).round() as u32).max(1);
+    image.resize_exact(image.width(), height, FilterType::Lanczos3)
+}
+
+///Parses a cell aspect, which has to be a finite number above 0.
+pub fn parse_cell_aspect(value: &str) -> Result<f32, String> {
+    let cell_aspect = value
+        .parse::<f32>()
+        .map_err(|_| format!("`{}` isn't a number", value))?;
+    if !cell_aspect.is_finite() || cell_aspect <= 0.0 {
+        return Err(format!(
+            "the cell aspect has to be a number above 0, got `{}`",
+            value
+        ));
+    }
+    Ok(cell_aspect)
+}
+
+#[cfg(test)]
+mod sizing_tests {
+    use super::*;
+
+    #[test]
+    fn fit_image() {
+        let image = DynamicImage::new_luma8(400, 200);
+        let fitted = super::fit_image(image.clone(), Some(80), None, (1.0, 2.0));
+        assert_eq!((fitted.width(), fitted.height()), (80, 40));
+
+        let fitted = super::fit_image(image.clone(), Some(80), Some(10), (1.0, 2.0));
+        assert_eq!((fitted.width(), fitted.height()), (40, 20));
+
+        let fitted = super::fit_image(image, None, Some(10), (2.0, 4.0));
+        assert_eq!((fitted.width(), fitted.height()), (80, 40));
+    }
+
+    #[test]
+    fn parse_cell_aspect() {
+        assert_eq!(super::parse_cell_aspect("2.2"), Ok(2.2));
+        assert!(super::parse_cell_aspect("0").is_err());
+        assert!(super::parse_cell_aspect("-1").is_err());
+        assert!(super::parse_cell_aspect("NaN").is_err());
+        assert!(super::parse_cell_aspect("inf").is_err());
+    }
+}
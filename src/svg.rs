@@ -0,0 +1,142 @@
+use image::{Rgb, Rgba};
+use rusttype::{Font, Scale};
+
+use crate::{
+    as_chars::GridLayout,
+    char_grid::{sanitize_font_family, Cell, CharGrid},
+};
+
+///Writes the chars as an SVG with every row as a `<text>` element and every run of equally
+///coloured chars as a `<tspan>`, placing each char on the same grid as [`crate::as_chars::as_chars_image`].
+///`font` is only used for measuring, the SVG itself asks for `font_family`.
+pub fn as_chars_svg(
+    chars: &CharGrid,
+    font: &Font,
+    scale: Scale,
+    layout: GridLayout,
+    foreground: Rgba<u8>,
+    background: Rgba<u8>,
+    font_family: &str,
+) -> String {
+    let (cell_width, cell_height) = layout.cell_size(chars, font, scale);
+    let padding = layout.padding as f32;
+    let width = cell_width * chars.width() as f32 + padding * 2.0;
+    let height = cell_height * chars.height() as f32 + padding * 2.0;
+    let ascent = font.v_metrics(scale).ascent;
+    //Scale is the height from descent to ascent, CSS font sizes are measured in ems.
+    let unscaled = font.v_metrics_unscaled();
+    let font_size = scale.y * font.units_per_em() as f32 / (unscaled.ascent - unscaled.descent);
+    let font_family = sanitize_font_family(font_family);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n",
+        width, height
+    );
+    if background[3] != u8::MIN {
+        svg.push_str(&format!(
+            "<rect width=\"100%\" height=\"100%\" {}/>\n",
+            paint(background)
+        ));
+    }
+    for (y, row) in chars.rows().enumerate() {
+        let top = padding + y as f32 * cell_height;
+        let mut x = 0;
+        for run in row.chunk_by(|a, b| a.background == b.background) {
+            if let Some(color) = run[0].background {
+                svg.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>\n",
+                    padding + x as f32 * cell_width,
+                    top,
+                    run.len() as f32 * cell_width,
+                    cell_height,
+                    paint(to_rgba(color))
+                ));
+            }
+            x += run.len();
+        }
+
+        svg.push_str(&format!(
+            "<text y=\"{}\" font-family=\"'{}', monospace\" font-size=\"{}\" {} xml:space=\"preserve\">",
+            top + ascent,
+            font_family,
+            font_size,
+            paint(foreground)
+        ));
+        let mut x = 0;
+        for run in row.chunk_by(|a, b| a.foreground == b.foreground) {
+            let positions = (x..x + run.len())
+                .map(|x| (padding + x as f32 * cell_width).to_string())
+                .collect::<Vec<String>>()
+                .join(" ");
+            svg.push_str(&format!("<tspan x=\"{}\"", positions));
+            if let Some(color) = run[0].foreground {
+                svg.push(' ');
+                svg.push_str(&paint(to_rgba(color)));
+            }
+            svg.push('>');
+            run.iter().for_each(|cell| push_escaped(&mut svg, cell));
+            svg.push_str("</tspan>");
+            x += run.len();
+        }
+        svg.push_str("</text>\n");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn to_rgba(color: Rgb<u8>) -> Rgba<u8> {
+    Rgba([color[0], color[1], color[2], u8::MAX])
+}
+
+fn paint(color: Rgba<u8>) -> String {
+    let fill = format!("fill=\"#{:02x}{:02x}{:02x}\"", color[0], color[1], color[2]);
+    if color[3] == u8::MAX {
+        fill
+    } else {
+        format!("{} fill-opacity=\"{}\"", fill, color[3] as f32 / 255.0)
+    }
+}
+
+fn push_escaped(svg: &mut String, cell: &Cell) {
+    match cell.char {
+        '&' => svg.push_str("&amp;"),
+        '<' => svg.push_str("&lt;"),
+        '>' => svg.push_str("&gt;"),
+        char => svg.push(char),
+    }
+}
+
+#[cfg(test)]
+mod svg_tests {
+    use super::*;
+    use crate::brightness_char_map::FONT;
+
+    #[test]
+    fn as_chars_svg() {
+        let font = Font::try_from_bytes(FONT).unwrap();
+        let red = Rgb([255, 0, 0]);
+        let chars = CharGrid::new(
+            3,
+            1,
+            vec![
+                Cell::new('<', 0).with_foreground(red),
+                Cell::new('b', 0).with_foreground(red),
+                Cell::new('c', 0).with_background(red),
+            ],
+        );
+        let svg = super::as_chars_svg(
+            &chars,
+            &font,
+            Scale::uniform(20.0),
+            GridLayout::default(),
+            Rgba([255, 255, 255, 255]),
+            Rgba([0, 0, 0, 0]),
+            "monospace",
+        );
+        assert!(svg.starts_with("<svg "));
+        assert!(!svg.contains("height=\"100%\""));
+        assert!(svg.contains(" fill=\"#ff0000\">&lt;b</tspan>"));
+        assert_eq!(svg.matches("<rect ").count(), 1);
+        assert_eq!(svg.matches("<text ").count(), 1);
+    }
+}
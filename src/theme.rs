@@ -0,0 +1,162 @@
+use std::str::FromStr;
+
+use image::{Rgb, Rgba};
+
+const OSC_11_QUERY: &str = "\x1b]11;?\x07";
+
+///Whether chars are drawn light on a dark background or dark on a light one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    Dark,
+    Light,
+    ///Asks the terminal for its background colour, falling back to dark.
+    Auto,
+}
+
+impl Theme {
+    ///Replaces [`Theme::Auto`] with the theme of the terminal.
+    pub fn resolve(self) -> Theme {
+        match self {
+            Self::Auto => match query_background() {
+                Some(background) if is_light(background) => Self::Light,
+                _ => Self::Dark,
+            },
+            theme => theme,
+        }
+    }
+
+    pub fn foreground(&self) -> Rgba<u8> {
+        match self {
+            Self::Light => Rgba([u8::MIN, u8::MIN, u8::MIN, u8::MAX]),
+            Self::Dark | Self::Auto => Rgba([u8::MAX, u8::MAX, u8::MAX, u8::MAX]),
+        }
+    }
+
+    pub fn background(&self) -> Rgba<u8> {
+        match self {
+            Self::Light => Rgba([u8::MAX, u8::MAX, u8::MAX, u8::MAX]),
+            Self::Dark | Self::Auto => Rgba([u8::MIN, u8::MIN, u8::MIN, u8::MAX]),
+        }
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(theme: &str) -> Result<Self, Self::Err> {
+        match theme.to_ascii_lowercase().as_str() {
+            "dark" => Ok(Self::Dark),
+            "light" => Ok(Self::Light),
+            "auto" => Ok(Self::Auto),
+            _ => Err(format!(
+                "unknown theme `{}`, expected dark, light or auto",
+                theme
+            )),
+        }
+    }
+}
+
+fn is_light(color: Rgb<u8>) -> bool {
+    let luma = 0.299 * color[0] as f32 + 0.587 * color[1] as f32 + 0.114 * color[2] as f32;
+    luma > u8::MAX as f32 / 2.0
+}
+
+///Parses the terminal's answer to an OSC 11 query, such as `\x1b]11;rgb:ffff/ffff/dddd\x07`.
+pub fn parse_osc_11(response: &str) -> Option<Rgb<u8>> {
+    let rgb = &response[response.find("rgb:")? + "rgb:".len()..];
+    let mut channels = rgb.split('/').map(|channel| {
+        let digits = channel
+            .chars()
+            .take_while(char::is_ascii_hexdigit)
+            .collect::<String>();
+        //XParseColor allows 1 to 4 hex digits per channel.
+        if !(1..=4).contains(&digits.len()) {
+            return None;
+        }
+        let max = 16u32.pow(digits.len() as u32) - 1;
+        let value = u32::from_str_radix(&digits, 16).ok()?;
+        Some((value * u8::MAX as u32 / max) as u8)
+    });
+    Some(Rgb([
+        channels.next()??,
+        channels.next()??,
+        channels.next()??,
+    ]))
+}
+
+///Sends an OSC 11 query to the terminal and waits briefly for its background colour.
+///Returns `None` when stdin or stdout isn't a terminal, or the terminal doesn't answer.
+#[cfg(unix)]
+fn query_background() -> Option<Rgb<u8>> {
+    use std::{
+        fs::OpenOptions,
+        io::{self, IsTerminal, Read, Write},
+    };
+
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return None;
+    }
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    let saved = stty(&["-g"])?;
+    //Without echo and line buffering, and giving up on reads after 0.2 seconds.
+    stty(&["-echo", "-icanon", "min", "0", "time", "2"])?;
+    let mut response = Vec::new();
+    let sent = tty
+        .write_all(OSC_11_QUERY.as_bytes())
+        .and_then(|_| tty.flush());
+    if sent.is_ok() {
+        let mut buffer = [0u8; 64];
+        while let Ok(read @ 1..) = tty.read(&mut buffer) {
+            response.extend_from_slice(&buffer[..read]);
+            if response.ends_with(b"\x07") || response.ends_with(b"\x1b\\") {
+                break;
+            }
+        }
+    }
+    stty(&[saved.trim()]);
+    parse_osc_11(&String::from_utf8_lossy(&response))
+}
+
+#[cfg(not(unix))]
+fn query_background() -> Option<Rgb<u8>> {
+    None
+}
+
+#[cfg(unix)]
+fn stty(args: &[&str]) -> Option<String> {
+    let tty = std::fs::File::open("/dev/tty").ok()?;
+    let output = std::process::Command::new("stty")
+        .args(args)
+        .stdin(tty)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod theme_tests {
+    use super::*;
+
+    #[test]
+    fn parse_osc_11() {
+        assert_eq!(
+            super::parse_osc_11("\x1b]11;rgb:ffff/8080/0000\x07"),
+            Some(Rgb([255, 128, 0]))
+        );
+        assert_eq!(
+            super::parse_osc_11("\x1b]11;rgb:f/0/8\x1b\\"),
+            Some(Rgb([255, 0, 136]))
+        );
+        assert_eq!(super::parse_osc_11(""), None);
+        assert_eq!(super::parse_osc_11("\x1b]11;rgb:fffffff/0/0\x07"), None);
+        assert!(is_light(Rgb([250, 250, 240])));
+        assert!(!is_light(Rgb([30, 30, 30])));
+    }
+}
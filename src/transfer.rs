@@ -0,0 +1,251 @@
+use std::{fs, io, path::Path, str::FromStr};
+
+use image::GrayImage;
+
+use crate::brightness_char_map::LUT_LENGTH;
+
+const DEFAULT_SIGMOID_MIDPOINT: f32 = 0.5;
+
+///Shapes how pixel brightness turns into glyph density.
+///Every curve maps a brightness from 0 to 1 onto a density from 0 to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransferCurve {
+    ///Raises the brightness to the power of `1 / gamma`, values above 1 favour the denser chars.
+    Gamma(f32),
+    ///S-curve around `midpoint`, spreading the mid tones over more chars the higher `gain` is.
+    Sigmoid { gain: f32, midpoint: f32 },
+    ///Straight lines between `(input, output)` points sorted by input.
+    Piecewise(Vec<(f32, f32)>),
+    ///Stretches the darkest and brightest pixel of the image onto the whole range.
+    AutoLevels,
+}
+
+impl TransferCurve {
+    ///Maps `value` for an image whose pixels range from `levels.0` to `levels.1`, all from 0 to 1.
+    pub fn apply(&self, value: f32, levels: (f32, f32)) -> f32 {
+        let value = value.clamp(0.0, 1.0);
+        match self {
+            Self::Gamma(gamma) => value.powf(1.0 / gamma),
+            Self::Sigmoid { gain, midpoint } => {
+                let sigmoid = |x: f32| 1.0 / (1.0 + (-gain * (x - midpoint)).exp());
+                let (low, high) = (sigmoid(0.0), sigmoid(1.0));
+                if high - low <= f32::EPSILON {
+                    value
+                } else {
+                    (sigmoid(value) - low) / (high - low)
+                }
+            }
+            Self::Piecewise(points) => interpolate(points, value),
+            Self::AutoLevels => {
+                let (low, high) = levels;
+                if high - low <= f32::EPSILON {
+                    value
+                } else {
+                    ((value - low) / (high - low)).clamp(0.0, 1.0)
+                }
+            }
+        }
+    }
+
+    ///Loads a piecewise curve from a file with an `input output` pair from 0 to 255 per line.
+    ///Pairs may also be separated by a comma, empty lines and lines starting with `#` are skipped.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<TransferCurve> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut points = Vec::new();
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad_point = || {
+                invalid(format!(
+                    "curve point `{}` isn't two numbers from 0 to 255",
+                    line
+                ))
+            };
+            let values = line
+                .split(|char: char| char == ',' || char.is_whitespace())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.parse::<u8>())
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| bad_point())?;
+            let [input, output] = values[..] else {
+                return Err(bad_point());
+            };
+            points.push((
+                input as f32 / u8::MAX as f32,
+                output as f32 / u8::MAX as f32,
+            ));
+        }
+        if points.is_empty() {
+            return Err(invalid("curve file has no points".to_string()));
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Self::Piecewise(points))
+    }
+}
+
+impl FromStr for TransferCurve {
+    type Err = String;
+
+    ///Parses `gamma=f32`, `sigmoid=gain[,midpoint]`, `piecewise=path` or `auto-levels`.
+    fn from_str(curve: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match curve.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (curve.trim(), None),
+        };
+        let number = |value: &str| {
+            value
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| format!("`{}` needs a number, got `{}`", name, value))
+        };
+        match (name.to_ascii_lowercase().as_str(), value) {
+            ("gamma", Some(gamma)) => Ok(Self::Gamma(number(gamma)?)),
+            ("sigmoid", Some(value)) => {
+                let (gain, midpoint) = match value.split_once(',') {
+                    Some((gain, midpoint)) => (number(gain)?, number(midpoint)?),
+                    None => (number(value)?, DEFAULT_SIGMOID_MIDPOINT),
+                };
+                Ok(Self::Sigmoid { gain, midpoint })
+            }
+            ("piecewise", Some(path)) => Self::load(path).map_err(|error| error.to_string()),
+            ("auto-levels", None) => Ok(Self::AutoLevels),
+            ("gamma" | "sigmoid" | "piecewise", None) => {
+                Err(format!("`{}` needs a value, as in {}=…", name, name))
+            }
+            _ => Err(format!(
+                "unknown curve `{}`, expected gamma=f32, sigmoid=gain[,midpoint], piecewise=path or auto-levels",
+                curve
+            )),
+        }
+    }
+}
+
+fn interpolate(points: &[(f32, f32)], value: f32) -> f32 {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return value;
+    };
+    if value <= first.0 {
+        return first.1;
+    }
+    for pair in points.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        if value <= x1 {
+            if x1 - x0 <= f32::EPSILON {
+                return y1;
+            }
+            return y0 + (value - x0) / (x1 - x0) * (y1 - y0);
+        }
+    }
+    last.1
+}
+
+///Builds the lookup from a pixel's brightness to the brightness whose char draws it.
+///The curves are applied in order and the result is spread over `ramp`, the darkest and
+///brightest char in the char map. [`TransferCurve::AutoLevels`] stretches the range the
+///curves before it left the image in.
+pub fn transfer_lut(
+    curves: &[TransferCurve],
+    image: &GrayImage,
+    ramp: (u8, u8),
+) -> [u8; LUT_LENGTH] {
+    let max = u8::MAX as f32;
+    let mut present = [false; LUT_LENGTH];
+    for value in image.iter() {
+        present[*value as usize] = true;
+    }
+    //The brightnesses in the image as every curve sees them, after the curves before it.
+    let mut values = (0..LUT_LENGTH)
+        .filter(|value| present[*value])
+        .map(|value| value as f32 / max)
+        .collect::<Vec<f32>>();
+    let mut levels = Vec::with_capacity(curves.len());
+    for curve in curves {
+        let curve_levels = values
+            .iter()
+            .fold(None, |range: Option<(f32, f32)>, value| match range {
+                Some((low, high)) => Some((low.min(*value), high.max(*value))),
+                None => Some((*value, *value)),
+            })
+            .unwrap_or((0.0, 1.0));
+        for value in &mut values {
+            *value = curve.apply(*value, curve_levels);
+        }
+        levels.push(curve_levels);
+    }
+    let (ramp_low, ramp_high) = (ramp.0 as f32, ramp.1 as f32);
+    std::array::from_fn(|value| {
+        let density = curves
+            .iter()
+            .zip(&levels)
+            .fold(value as f32 / max, |density, (curve, levels)| {
+                curve.apply(density, *levels)
+            });
+        (ramp_low + density.clamp(0.0, 1.0) * (ramp_high - ramp_low)).round() as u8
+    })
+}
+
+#[cfg(test)]
+mod transfer_tests {
+    use image::Luma;
+
+    use super::*;
+
+    #[test]
+    fn curves() {
+        assert_eq!("gamma=2".parse(), Ok(TransferCurve::Gamma(2.0)));
+        assert_eq!(
+            "sigmoid=8,0.4".parse(),
+            Ok(TransferCurve::Sigmoid {
+                gain: 8.0,
+                midpoint: 0.4
+            })
+        );
+        assert!("sigmoid".parse::<TransferCurve>().is_err());
+
+        let sigmoid = TransferCurve::Sigmoid {
+            gain: 10.0,
+            midpoint: 0.5,
+        };
+        assert!(sigmoid.apply(0.0, (0.0, 1.0)).abs() < 1e-4);
+        assert!((sigmoid.apply(0.5, (0.0, 1.0)) - 0.5).abs() < 1e-4);
+        assert!(sigmoid.apply(0.6, (0.0, 1.0)) > 0.6);
+
+        let piecewise = TransferCurve::Piecewise(vec![(0.0, 0.0), (0.5, 0.8), (1.0, 1.0)]);
+        assert!((piecewise.apply(0.25, (0.0, 1.0)) - 0.4).abs() < 1e-4);
+        assert!((piecewise.apply(0.75, (0.0, 1.0)) - 0.9).abs() < 1e-4);
+    }
+
+    #[test]
+    fn load() {
+        let path = std::env::temp_dir().join("char_art_transfer_test.txt");
+        fs::write(&path, "# input output\n255, 200\n0 10\n").unwrap();
+        let curve = TransferCurve::load(&path).unwrap();
+        fs::write(&path, "0 10 20\n").unwrap();
+        let error = TransferCurve::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            curve,
+            TransferCurve::Piecewise(vec![(0.0, 10.0 / 255.0), (1.0, 200.0 / 255.0)])
+        );
+        assert!(error.is_err());
+    }
+
+    #[test]
+    fn transfer_lut() {
+        let image = GrayImage::from_fn(2, 1, |x, _| Luma([100 + x as u8 * 50]));
+        let lut = super::transfer_lut(&[TransferCurve::AutoLevels], &image, (10, 110));
+        assert_eq!(lut[100], 10);
+        assert_eq!(lut[125], 60);
+        assert_eq!(lut[150], 110);
+        assert_eq!(lut[255], 110);
+
+        //Auto levels stretches the range the gamma curve left, not the original one.
+        let curves = [TransferCurve::Gamma(0.5), TransferCurve::AutoLevels];
+        let lut = super::transfer_lut(&curves, &image, (0, 255));
+        assert_eq!(lut[100], 0);
+        assert_eq!(lut[150], 255);
+    }
+}
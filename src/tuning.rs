@@ -0,0 +1,187 @@
+use std::str::FromStr;
+
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+use rusttype::{Font, Scale};
+
+use crate::{
+    as_chars::{as_chars_image, GridLayout},
+    brightness_char_map::BrightnessCharMap,
+    char_grid::CharGrid,
+    preprocess::adjust_gamma,
+    shape_char_map::ssim,
+    theme::Theme,
+};
+
+pub const DARKEN_CANDIDATES: [i32; 5] = [-64, -32, 0, 32, 64];
+pub const GAMMA_CANDIDATES: [f32; 5] = [0.6, 0.8, 1.0, 1.25, 1.6];
+///A short ramp that often reads better than the full set of chars on small outputs.
+pub const SIMPLE_CHARSET: &str = " .:-=+*#%@";
+const SSIM_WINDOW: u32 = 8;
+
+///How the rendered chars are compared with the source image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityMetric {
+    ///Peak signal-to-noise ratio in decibels.
+    Psnr,
+    ///Mean structural similarity over 8x8 windows.
+    Ssim,
+}
+
+impl QualityMetric {
+    ///Scores how close `a` is to `b`, higher is better.
+    ///
+    ///# Panics
+    ///Panics if the images aren't the same size.
+    pub fn score(&self, a: &GrayImage, b: &GrayImage) -> f32 {
+        match self {
+            Self::Psnr => psnr(a, b),
+            Self::Ssim => mean_ssim(a, b),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Psnr => "psnr",
+            Self::Ssim => "ssim",
+        }
+    }
+}
+
+impl FromStr for QualityMetric {
+    type Err = String;
+
+    fn from_str(metric: &str) -> Result<Self, Self::Err> {
+        match metric.to_ascii_lowercase().as_str() {
+            "psnr" => Ok(Self::Psnr),
+            "ssim" => Ok(Self::Ssim),
+            _ => Err(format!(
+                "unknown metric `{}`, expected psnr or ssim",
+                metric
+            )),
+        }
+    }
+}
+
+pub fn psnr(a: &GrayImage, b: &GrayImage) -> f32 {
+    assert_eq!(a.dimensions(), b.dimensions(), "images differ in size");
+    let squared_error = a
+        .pixels()
+        .zip(b.pixels())
+        .map(|(a, b)| (a[0] as f32 - b[0] as f32).powi(2))
+        .sum::<f32>();
+    let mse = squared_error / (a.width() * a.height()).max(1) as f32;
+    if mse == 0.0 {
+        return f32::INFINITY;
+    }
+    10.0 * (u8::MAX as f32).powi(2).log10() - 10.0 * mse.log10()
+}
+
+///Averages [`ssim`] over every 8x8 window, cut short at the right and bottom edge.
+pub fn mean_ssim(a: &GrayImage, b: &GrayImage) -> f32 {
+    assert_eq!(a.dimensions(), b.dimensions(), "images differ in size");
+    let window = |image: &GrayImage, left: u32, top: u32| {
+        let mut tile = Vec::with_capacity((SSIM_WINDOW * SSIM_WINDOW) as usize);
+        for y in top..(top + SSIM_WINDOW).min(image.height()) {
+            for x in left..(left + SSIM_WINDOW).min(image.width()) {
+                tile.push(image.get_pixel(x, y)[0] as f32 / u8::MAX as f32);
+            }
+        }
+        tile
+    };
+    let (mut sum, mut windows) = (0.0, 0);
+    for top in (0..a.height()).step_by(SSIM_WINDOW as usize) {
+        for left in (0..a.width()).step_by(SSIM_WINDOW as usize) {
+            sum += ssim(&window(a, left, top), &window(b, left, top));
+            windows += 1;
+        }
+    }
+    sum / windows.max(1) as f32
+}
+
+///The settings [`AutoTuner::tune`] picked and how well they scored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tuning {
+    pub darken: i32,
+    pub gamma: f32,
+    ///Index of the chosen char map in [`AutoTuner::char_maps`].
+    pub char_map: usize,
+    pub score: f32,
+}
+
+///Renders every combination of darken amount, gamma and char map and keeps the one that
+///looks most like the source image.
+pub struct AutoTuner<'a> {
+    pub metric: QualityMetric,
+    ///The char maps to try, with the charset to report for each one (`None` for the default).
+    pub char_maps: Vec<(Option<String>, BrightnessCharMap)>,
+    pub font: Font<'a>,
+    pub scale: Scale,
+    ///Which colours the candidates are rendered in.
+    pub theme: Theme,
+}
+
+impl AutoTuner<'_> {
+    ///`to_chars` converts an adjusted copy of `image` with one of the char maps.
+    ///`image` should already be sized so every pixel becomes a char.
+    pub fn tune(
+        &self,
+        image: &DynamicImage,
+        to_chars: impl Fn(&DynamicImage, &BrightnessCharMap) -> CharGrid,
+    ) -> Tuning {
+        let mut best = Tuning {
+            darken: 0,
+            gamma: 1.0,
+            char_map: 0,
+            score: f32::NEG_INFINITY,
+        };
+        let source = image.to_luma8();
+        for (index, (_, char_map)) in self.char_maps.iter().enumerate() {
+            for darken in DARKEN_CANDIDATES {
+                for gamma in GAMMA_CANDIDATES {
+                    let adjusted = adjust_gamma(image.brighten(-darken), gamma);
+                    let rendered = as_chars_image(
+                        &to_chars(&adjusted, char_map),
+                        &self.font,
+                        self.scale,
+                        GridLayout::default(),
+                        self.theme.foreground(),
+                        self.theme.background(),
+                    );
+                    //Compared at the size of the source, roughly how it looks from a distance.
+                    let rendered = DynamicImage::ImageRgba8(rendered)
+                        .resize_exact(image.width(), image.height(), FilterType::Triangle)
+                        .into_luma8();
+                    let score = self.metric.score(&rendered, &source);
+                    if score > best.score {
+                        best = Tuning {
+                            darken,
+                            gamma,
+                            char_map: index,
+                            score,
+                        };
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tuning_tests {
+    use image::Luma;
+
+    use super::*;
+
+    #[test]
+    fn metrics() {
+        let a = GrayImage::from_fn(16, 16, |x, _| Luma([x as u8 * 16]));
+        let mut b = a.clone();
+        assert_eq!(psnr(&a, &b), f32::INFINITY);
+        assert!((mean_ssim(&a, &b) - 1.0).abs() < 1e-4);
+
+        b.put_pixel(0, 0, Luma([10]));
+        assert!((psnr(&a, &b) - 52.21).abs() < 0.01);
+        assert!(mean_ssim(&a, &b) < 1.0);
+    }
+}